@@ -28,10 +28,9 @@ impl<'a> PaletteEntry<'a> {
         }
 
         let s = s.trim();
-        if s == "" {
+        if s.is_empty() {
             None
-        } else if s.starts_with('#') {
-            let s = &s[1..];
+        } else if let Some(s) = s.strip_prefix('#') {
             if s.len() != 6 && s.len() != 8 {
                 return None;
             }
@@ -0,0 +1,543 @@
+use num_enum::TryFromPrimitive;
+use serde::Serialize;
+use std::convert::TryInto;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::authorization::{DcKey, MtpAuthorization};
+use crate::background::{self, Background};
+use crate::data::PeerId;
+use crate::descriptor::{parse_blob, EncryptedDescriptor, FileReadDescriptor, StreamWithEnd};
+use crate::document::{self, StoredDocument};
+use crate::drafts::{self, PeerDrafts};
+use crate::error::{Error, Result};
+use crate::export_settings::{self, ExportSettings};
+use crate::legacy_media::{self, LegacyMedia, LegacyMediaKind};
+use crate::locations::{self, FileLocations};
+use crate::peer::SelfUser;
+use crate::recent::{self, RecentHashtagsAndBots, TrustedBot};
+use crate::schema::Setting;
+use crate::session_export::SessionExport;
+use crate::session_settings::SessionSettings;
+use crate::stickers::{self, StickerSets, StickerSetsFile};
+use crate::{compose_data_string, FileKey, MtpAuthKey};
+
+pub struct Account {
+    data_name: String,
+    index: i32,
+    pub(crate) local: StorageAccount,
+    pub(crate) session_settings: Option<SessionSettings>,
+    pub(crate) authorization: Option<MtpAuthorization>,
+}
+
+impl Account {
+    pub(crate) fn new(data_name: &str, index: i32, base_path: &Path) -> Self {
+        Self {
+            data_name: data_name.to_owned(),
+            index,
+            local: StorageAccount::new(compose_data_string(data_name, index), base_path),
+            session_settings: None,
+            authorization: None,
+        }
+    }
+
+    pub(crate) fn prepare_to_start(&mut self, local_key: Rc<MtpAuthKey>) -> Result<()> {
+        self.local.start(local_key)
+    }
+
+    pub fn data_name(&self) -> &str {
+        &self.data_name
+    }
+
+    pub fn index(&self) -> i32 {
+        self.index
+    }
+
+    /// The settings from this account's settings file, if it has any.
+    pub fn session_settings(&self) -> Option<&SessionSettings> {
+        self.session_settings.as_ref()
+    }
+
+    /// The user and keys this account is logged in with, if it is logged in at all.
+    pub fn authorization(&self) -> Option<&MtpAuthorization> {
+        self.authorization.as_ref()
+    }
+
+    /// The session for this account's main DC, ready to be exported to other clients.
+    pub fn export_session(&self) -> Option<SessionExport> {
+        SessionExport::from_authorization(self.authorization.as_ref()?)
+    }
+
+    /// Reads the unsent and unfinished edit drafts of every chat, along with their cursors.
+    pub fn read_drafts(&self) -> Result<Vec<PeerDrafts>> {
+        self.local.read_drafts()
+    }
+
+    /// Reads one of the account's sticker set files, if it has one.
+    pub fn read_sticker_sets(&self, file: StickerSetsFile) -> Result<Option<StickerSets>> {
+        self.local.read_sticker_sets(file)
+    }
+
+    /// Reads the account's saved GIFs, if it has any.
+    pub fn read_saved_gifs(&self) -> Result<Option<Vec<StoredDocument>>> {
+        self.local.read_saved_gifs()
+    }
+
+    /// Reads the hashtags and inline bots recently used by the account, if it has any.
+    pub fn read_recent_hashtags_and_bots(&self) -> Result<Option<RecentHashtagsAndBots>> {
+        self.local.read_recent_hashtags_and_bots()
+    }
+
+    /// Reads the bots the account has trusted, if there are any.
+    pub fn read_trusted_bots(&self) -> Result<Option<Vec<TrustedBot>>> {
+        self.local.read_trusted_bots()
+    }
+
+    /// The logged-in user, as the account last saw them, unless their saved data couldn't be read.
+    pub fn self_user(&self) -> Option<&SelfUser> {
+        self.local.self_user.as_ref()
+    }
+
+    /// The keys of every file listed in the account's map.
+    pub fn storage_map(&self) -> &StorageMap {
+        &self.local.keys
+    }
+
+    /// Decrypts the file behind a record from one of the legacy media maps in the [`StorageMap`].
+    ///
+    /// Returns `None` if the file is gone, or holds media for some other location.
+    pub fn read_legacy_media(
+        &self,
+        kind: LegacyMediaKind,
+        record: &LegacyFileRecord,
+    ) -> Result<Option<LegacyMedia>> {
+        self.local.read_legacy_media(kind, record)
+    }
+
+    /// Every record from the legacy media maps, along with the map each came from.
+    pub fn legacy_media_records(
+        &self,
+    ) -> impl Iterator<Item = (LegacyMediaKind, &LegacyFileRecord)> {
+        let keys = &self.local.keys;
+        let tag = |kind| move |record| (kind, record);
+        keys.legacy_images
+            .iter()
+            .map(tag(LegacyMediaKind::Image))
+            .chain(
+                keys.legacy_sticker_images
+                    .iter()
+                    .map(tag(LegacyMediaKind::StickerImage)),
+            )
+            .chain(keys.legacy_audios.iter().map(tag(LegacyMediaKind::Audio)))
+    }
+
+    /// Reads the index of files the account has downloaded, if it has one.
+    pub fn read_locations(&self) -> Result<Option<FileLocations>> {
+        self.local.read_locations()
+    }
+
+    /// Reads the options the chat exporter was last run with, if it ever was.
+    pub fn read_export_settings(&self) -> Result<Option<ExportSettings>> {
+        self.local.read_export_settings()
+    }
+
+    /// Reads the background this account used for day or `night` mode, before backgrounds became global.
+    pub fn read_legacy_background(&self, night: bool) -> Result<Option<Background>> {
+        self.local.read_legacy_background(night)
+    }
+
+    /// The directory holding this account's files.
+    pub fn base_path(&self) -> PathBuf {
+        self.local.root.join(&self.local.dir)
+    }
+}
+
+/// A record in one of the legacy media maps, pointing at a file that caches one download.
+#[derive(Debug, Clone, Serialize)]
+pub struct LegacyFileRecord {
+    pub key: FileKey,
+    /// The `StorageKey` the media was downloaded from.
+    pub location: (u64, u64),
+    pub size: u32,
+}
+
+/// Every file key listed in an account's map, with empty keys for entries it lacked.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct StorageMap {
+    pub drafts: Vec<(PeerId, FileKey)>,
+    pub draft_cursors: Vec<(PeerId, FileKey)>,
+    pub legacy_images: Vec<LegacyFileRecord>,
+    pub legacy_sticker_images: Vec<LegacyFileRecord>,
+    pub legacy_audios: Vec<LegacyFileRecord>,
+    /// Per-peer files from the legacy `UserMap` entry, only written by very old versions.
+    pub legacy_user_map: Vec<(PeerId, FileKey)>,
+    pub locations: FileKey,
+    pub settings: FileKey,
+    pub recent_hashtags_and_bots: FileKey,
+    pub installed_stickers: FileKey,
+    pub featured_stickers: FileKey,
+    pub recent_stickers: FileKey,
+    pub archived_stickers: FileKey,
+    pub installed_masks: FileKey,
+    pub recent_masks: FileKey,
+    pub archived_masks: FileKey,
+    pub faved_stickers: FileKey,
+    pub saved_gifs: FileKey,
+    pub trusted_bots: FileKey,
+    pub export_settings: FileKey,
+    pub background_day: FileKey,
+    pub background_night: FileKey,
+    pub recent_stickers_old: FileKey,
+    pub background_old_old: FileKey,
+    pub stickers_old: FileKey,
+    pub saved_peers_old: FileKey,
+    pub report_spam_statuses_old: FileKey,
+    pub saved_gifs_old: FileKey,
+}
+
+pub(crate) struct StorageAccount {
+    local_key: Rc<MtpAuthKey>,
+    /// The tdata directory.
+    root: PathBuf,
+    /// This account's directory, relative to `root`.
+    dir: PathBuf,
+    keys: StorageMap,
+    self_user: Option<SelfUser>,
+}
+
+impl StorageAccount {
+    fn new(data_name: String, root: &Path) -> Self {
+        let data_name_key = FileKey::compute(&data_name);
+        Self {
+            local_key: Rc::new(MtpAuthKey::BLANK),
+            root: root.to_owned(),
+            dir: PathBuf::from(data_name_key.to_file_part()),
+            keys: StorageMap::default(),
+            self_user: None,
+        }
+    }
+
+    fn open(&self, name: impl AsRef<Path>) -> Result<FileReadDescriptor> {
+        FileReadDescriptor::open(self.dir.join(name), &self.root)
+    }
+
+    fn start(&mut self, local_key: Rc<MtpAuthKey>) -> Result<()> {
+        self.local_key = local_key;
+        self.read_map()
+    }
+
+    // this originally accepted a local key, but... it's already in self
+    // pls
+    fn read_map(&mut self) -> Result<()> {
+        let mut map_data = self.open("map")?;
+
+        let _legacy_salt = map_data.read_bytes()?;
+        let _legacy_key_encrypted = map_data.read_bytes()?;
+
+        // there's a big "if !localKey" block here. I'm going to ignore it for now.
+
+        let mut map = map_data.read_encrypted(&self.local_key)?;
+        map_data.should_be_done()?;
+
+        while !map.is_done() {
+            let key_location = map.location();
+            let key_type: LocalStorageKey = map.read_val::<u32>()?.try_into().map_err(|_| {
+                Error::invalid_data(key_location.clone(), "unknown key type in encrypted map")
+            })?;
+            use LocalStorageKey::*;
+            match key_type {
+                Draft => {
+                    let count = map.read_val::<u32>()?;
+                    for _ in 0..count {
+                        let key = FileKey(map.read_val()?);
+                        let peer = map.read_val::<PeerId>()?;
+                        self.keys.drafts.push((peer, key));
+                    }
+                }
+                SelfSerialized => {
+                    let serialized = map.read_bytes()?;
+                    let version = map_data.version();
+                    // like `readSelf`, a blob that doesn't parse just leaves the user unknown
+                    self.self_user =
+                        parse_blob(&serialized, key_location, |s| SelfUser::read(s, version)).ok();
+                }
+                DraftPosition => {
+                    let count = map.read_val::<u32>()?;
+                    for _ in 0..count {
+                        let key = FileKey(map.read_val()?);
+                        let peer = map.read_val::<PeerId>()?;
+                        self.keys.draft_cursors.push((peer, key));
+                    }
+                }
+                LegacyImages | LegacyStickerImages | LegacyAudios => {
+                    let records = match key_type {
+                        LegacyImages => &mut self.keys.legacy_images,
+                        LegacyStickerImages => &mut self.keys.legacy_sticker_images,
+                        _ => &mut self.keys.legacy_audios,
+                    };
+                    let count = map.read_val::<u32>()?;
+                    for _ in 0..count {
+                        records.push(LegacyFileRecord {
+                            key: FileKey(map.read_val()?),
+                            location: map.read_val()?,
+                            size: map.read_val()?,
+                        });
+                    }
+                }
+                RecentHashtagsAndBots => {
+                    self.keys.recent_hashtags_and_bots = FileKey(map.read_val()?);
+                }
+                TrustedBots => {
+                    self.keys.trusted_bots = FileKey(map.read_val()?);
+                }
+                FavedStickers => {
+                    self.keys.faved_stickers = FileKey(map.read_val()?);
+                }
+                SavedGifs => {
+                    self.keys.saved_gifs = FileKey(map.read_val()?);
+                }
+                UserSettings => {
+                    self.keys.settings = FileKey(map.read_val()?);
+                }
+                Locations => self.keys.locations = FileKey(map.read_val()?),
+                ExportSettings => self.keys.export_settings = FileKey(map.read_val()?),
+                BackgroundOld => {
+                    self.keys.background_day = FileKey(map.read_val()?);
+                    self.keys.background_night = FileKey(map.read_val()?);
+                }
+                RecentStickersOld => self.keys.recent_stickers_old = FileKey(map.read_val()?),
+                BackgroundOldOld => self.keys.background_old_old = FileKey(map.read_val()?),
+                StickersOld => self.keys.stickers_old = FileKey(map.read_val()?),
+                SavedPeersOld => self.keys.saved_peers_old = FileKey(map.read_val()?),
+                ReportSpamStatusesOld => {
+                    self.keys.report_spam_statuses_old = FileKey(map.read_val()?)
+                }
+                SavedGifsOld => self.keys.saved_gifs_old = FileKey(map.read_val()?),
+                StickersKeys => {
+                    self.keys.installed_stickers = FileKey(map.read_val()?);
+                    self.keys.featured_stickers = FileKey(map.read_val()?);
+                    self.keys.recent_stickers = FileKey(map.read_val()?);
+                    self.keys.archived_stickers = FileKey(map.read_val()?);
+                }
+                MasksKeys => {
+                    self.keys.installed_masks = FileKey(map.read_val()?);
+                    self.keys.recent_masks = FileKey(map.read_val()?);
+                    self.keys.archived_masks = FileKey(map.read_val()?);
+                }
+                UserMap => {
+                    let count = map.read_val::<u32>()?;
+                    for _ in 0..count {
+                        let key = FileKey(map.read_val()?);
+                        let peer = map.read_val::<PeerId>()?;
+                        self.keys.legacy_user_map.push((peer, key));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn read_session_settings(&self) -> Result<Option<SessionSettings>> {
+        if self.keys.settings.is_empty() {
+            return Ok(None);
+        }
+
+        let mut settings_data = self.open(self.keys.settings.to_file_part())?;
+        let mut stream = settings_data.read_encrypted(&self.local_key)?;
+
+        let mut result = None;
+        while !stream.is_done() {
+            let location = stream.location();
+            if let Setting::SessionSettings { serialized } = stream.read_val::<Setting>()? {
+                result = Some(SessionSettings::from_serialized(
+                    &serialized,
+                    settings_data.version(),
+                    location,
+                )?);
+            }
+        }
+
+        Ok(result)
+    }
+
+    pub(crate) fn read_drafts(&self) -> Result<Vec<PeerDrafts>> {
+        let mut result = Vec::new();
+        for &(peer, key) in &self.keys.drafts {
+            let mut draft_data = match self.open(key.to_file_part()) {
+                Ok(draft_data) => draft_data,
+                // tdesktop forgets about drafts whose files have gone missing
+                Err(Error::NotFound { .. }) => continue,
+                Err(e) => return Err(e),
+            };
+            let mut stream = draft_data.read_encrypted(&self.local_key)?;
+            let mut drafts = drafts::read_drafts(&mut stream, draft_data.version(), peer)?;
+            if let Some(mut cursors) = self.open_draft_cursors(peer)? {
+                let mut stream = cursors.read_encrypted(&self.local_key)?;
+                drafts::read_draft_cursors(&mut stream, peer, &mut drafts)?;
+            }
+            if !drafts.is_empty() {
+                result.push(PeerDrafts { peer, drafts });
+            }
+        }
+        Ok(result)
+    }
+
+    /// Opens the file behind `key`, treating an empty key or a missing file as no file at all.
+    fn open_optional(&self, key: FileKey) -> Result<Option<FileReadDescriptor>> {
+        if key.is_empty() {
+            return Ok(None);
+        }
+        match self.open(key.to_file_part()) {
+            Ok(file) => Ok(Some(file)),
+            Err(Error::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Decrypts the file behind `key` with the local key and hands it to `read`, if the file exists.
+    fn read_optional<T>(
+        &self,
+        key: FileKey,
+        read: impl FnOnce(&mut EncryptedDescriptor, i32) -> Result<T>,
+    ) -> Result<Option<T>> {
+        let mut file = match self.open_optional(key)? {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let mut stream = file.read_encrypted(&self.local_key)?;
+        read(&mut stream, file.version()).map(Some)
+    }
+
+    pub(crate) fn read_sticker_sets(&self, file: StickerSetsFile) -> Result<Option<StickerSets>> {
+        let key = match file {
+            StickerSetsFile::Installed => self.keys.installed_stickers,
+            StickerSetsFile::Featured => self.keys.featured_stickers,
+            StickerSetsFile::Recent => self.keys.recent_stickers,
+            StickerSetsFile::Archived => self.keys.archived_stickers,
+            StickerSetsFile::InstalledMasks => self.keys.installed_masks,
+            StickerSetsFile::RecentMasks => self.keys.recent_masks,
+            StickerSetsFile::ArchivedMasks => self.keys.archived_masks,
+            StickerSetsFile::Faved => self.keys.faved_stickers,
+        };
+        self.read_optional(key, stickers::read_sticker_sets)
+    }
+
+    pub(crate) fn read_saved_gifs(&self) -> Result<Option<Vec<StoredDocument>>> {
+        self.read_optional(self.keys.saved_gifs, document::read_saved_gifs)
+    }
+
+    pub(crate) fn read_recent_hashtags_and_bots(&self) -> Result<Option<RecentHashtagsAndBots>> {
+        self.read_optional(
+            self.keys.recent_hashtags_and_bots,
+            recent::read_recent_hashtags_and_bots,
+        )
+    }
+
+    pub(crate) fn read_trusted_bots(&self) -> Result<Option<Vec<TrustedBot>>> {
+        self.read_optional(self.keys.trusted_bots, recent::read_trusted_bots)
+    }
+
+    pub(crate) fn read_legacy_media(
+        &self,
+        kind: LegacyMediaKind,
+        record: &LegacyFileRecord,
+    ) -> Result<Option<LegacyMedia>> {
+        let media = self.read_optional(record.key, |stream, _| {
+            legacy_media::read_legacy_media(stream, kind, record.location)
+        })?;
+        Ok(media.flatten())
+    }
+
+    pub(crate) fn read_locations(&self) -> Result<Option<FileLocations>> {
+        self.read_optional(self.keys.locations, locations::read_locations)
+    }
+
+    pub(crate) fn read_legacy_background(&self, night: bool) -> Result<Option<Background>> {
+        let key = if night {
+            self.keys.background_night
+        } else {
+            self.keys.background_day
+        };
+        self.read_optional(key, |stream, _| background::read_legacy_background(stream))
+    }
+
+    pub(crate) fn read_export_settings(&self) -> Result<Option<ExportSettings>> {
+        self.read_optional(self.keys.export_settings, |stream, _| {
+            export_settings::read_export_settings(stream)
+        })
+    }
+
+    fn open_draft_cursors(&self, peer: PeerId) -> Result<Option<FileReadDescriptor>> {
+        let key = match self.keys.draft_cursors.iter().find(|(p, _)| *p == peer) {
+            Some(&(_, key)) => key,
+            None => return Ok(None),
+        };
+        self.open_optional(key)
+    }
+
+    /// Reads the account's MTProto data, which lives next to its directory rather than inside it.
+    pub(crate) fn read_mtp_data(&self) -> Result<Option<MtpAuthorization>> {
+        let mut mtp_data = match FileReadDescriptor::open(&self.dir, &self.root) {
+            Ok(mtp_data) => mtp_data,
+            Err(Error::NotFound { .. }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut stream = mtp_data.read_encrypted(&self.local_key)?;
+
+        let mut result = None;
+        let mut legacy_user = None;
+        let mut legacy_keys = Vec::new();
+        while !stream.is_done() {
+            let location = stream.location();
+            match stream.read_val::<Setting>()? {
+                Setting::MtpAuthorization { serialized } => {
+                    result = Some(MtpAuthorization::from_serialized(&serialized, location)?);
+                }
+                Setting::User { user_id, dc_id } => legacy_user = Some((user_id, dc_id as i32)),
+                Setting::Key { dc_id, key } => legacy_keys.push(DcKey { dc_id, key }),
+                _ => {}
+            }
+        }
+
+        if result.is_none() {
+            if let Some((user_id, main_dc_id)) = legacy_user {
+                result = Some(MtpAuthorization::from_legacy(
+                    user_id,
+                    main_dc_id,
+                    legacy_keys,
+                ));
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+#[derive(Debug, TryFromPrimitive)]
+#[repr(u32)]
+enum LocalStorageKey {
+    UserMap = 0x00,               // legacy
+    Draft = 0x01,                 // data: PeerId peer
+    DraftPosition = 0x02,         // data: PeerId peer
+    LegacyImages = 0x03,          // legacy
+    Locations = 0x04,             // no data
+    LegacyStickerImages = 0x05,   // legacy
+    LegacyAudios = 0x06,          // legacy
+    RecentStickersOld = 0x07,     // no data
+    BackgroundOldOld = 0x08,      // no data
+    UserSettings = 0x09,          // no data
+    RecentHashtagsAndBots = 0x0a, // no data
+    StickersOld = 0x0b,           // no data
+    SavedPeersOld = 0x0c,         // no data
+    ReportSpamStatusesOld = 0x0d, // no data
+    SavedGifsOld = 0x0e,          // no data
+    SavedGifs = 0x0f,             // no data
+    StickersKeys = 0x10,          // no data
+    TrustedBots = 0x11,           // no data
+    FavedStickers = 0x12,         // no data
+    ExportSettings = 0x13,        // no data
+    BackgroundOld = 0x14,         // no data
+    SelfSerialized = 0x15,        // serialized self
+    MasksKeys = 0x16,             // no data
+}
@@ -0,0 +1,131 @@
+use std::rc::Rc;
+
+use crate::data::UserId;
+use crate::descriptor::{parse_blob, read_list, Blob, ValueStream};
+use crate::error::{Location, Result};
+use crate::MtpAuthKey;
+
+/// Which account a tdata belongs to, and the keys it is logged in with.
+#[derive(Debug)]
+pub struct MtpAuthorization {
+    pub user_id: UserId,
+    pub main_dc_id: i32,
+    /// The permanent auth key for each DC the account has connected to.
+    ///
+    /// Media DCs share the auth key of the DC they belong to, so they have no entries of their own.
+    pub keys: Vec<DcKey>,
+    /// Keys that tdesktop still has to destroy on the server, e.g. after logging out.
+    pub keys_to_destroy: Vec<DcKey>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DcKey {
+    pub dc_id: i32,
+    pub key: Rc<MtpAuthKey>,
+}
+
+/// Written in place of the 32-bit user id and main DC id, announcing that the 64-bit forms follow.
+const WIDE_IDS_TAG: u64 = !0;
+
+impl MtpAuthorization {
+    /// Parses the blob stored in a `MtpAuthorization` setting, which was read from `location`.
+    pub(crate) fn from_serialized(serialized: &[u8], location: Location) -> Result<Self> {
+        parse_blob(serialized, location, Self::read)
+    }
+
+    fn read(stream: &mut Blob<'_>) -> std::io::Result<Self> {
+        let legacy_user_id = stream.read_val::<i32>()?;
+        let legacy_main_dc_id = stream.read_val::<i32>()?;
+
+        let legacy_ids = (legacy_user_id as u32 as u64) << 32 | legacy_main_dc_id as u32 as u64;
+        let (user_id, main_dc_id) = if legacy_ids == WIDE_IDS_TAG {
+            (stream.read_val()?, stream.read_val()?)
+        } else {
+            (legacy_user_id as u32 as u64, legacy_main_dc_id)
+        };
+
+        let keys = read_keys(stream)?;
+        let keys_to_destroy = read_keys(stream)?;
+
+        Ok(Self {
+            user_id: UserId::new(user_id),
+            main_dc_id,
+            keys,
+            keys_to_destroy,
+        })
+    }
+
+    /// Builds an authorization out of the separate `User` and `Key` settings used before
+    /// `MtpAuthorization` existed.
+    pub(crate) fn from_legacy(user_id: i32, main_dc_id: i32, keys: Vec<DcKey>) -> Self {
+        Self {
+            user_id: UserId::new(user_id as u32 as u64),
+            main_dc_id,
+            keys,
+            keys_to_destroy: Vec::new(),
+        }
+    }
+
+    /// The key for the account's main DC, which is the one a session needs.
+    pub fn main_key(&self) -> Option<&Rc<MtpAuthKey>> {
+        self.keys
+            .iter()
+            .find(|k| k.dc_id == self.main_dc_id)
+            .map(|k| &k.key)
+    }
+}
+
+fn read_keys(stream: &mut Blob<'_>) -> std::io::Result<Vec<DcKey>> {
+    read_list(stream, |s| {
+        Ok(DcKey {
+            dc_id: s.read_val()?,
+            key: s.read_val()?,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::ValueSink;
+    use std::io::Write;
+
+    fn write_key(data: &mut Vec<u8>, dc_id: i32, fill: u8) {
+        data.write_val(&dc_id).unwrap();
+        data.write_all(&[fill; 256]).unwrap();
+    }
+
+    #[test]
+    fn read_wide_ids() {
+        let mut data = Vec::new();
+        data.write_val(&(WIDE_IDS_TAG, 5_000_000_000u64, 2i32))
+            .unwrap();
+        data.write_val(&2i32).unwrap();
+        write_key(&mut data, 1, 0x11);
+        write_key(&mut data, 2, 0x22);
+        data.write_val(&1i32).unwrap();
+        write_key(&mut data, 4, 0x44);
+
+        let auth = MtpAuthorization::from_serialized(&data, Location::default()).unwrap();
+        assert_eq!(auth.user_id, UserId::new(5_000_000_000));
+        assert_eq!(auth.main_dc_id, 2);
+        assert_eq!(auth.keys.len(), 2);
+        assert_eq!(auth.main_key().unwrap().data(), &[0x22; 256]);
+        assert_eq!(auth.keys_to_destroy.len(), 1);
+        assert_eq!(auth.keys_to_destroy[0].dc_id, 4);
+    }
+
+    #[test]
+    fn read_legacy_ids() {
+        let mut data = Vec::new();
+        data.write_val(&(123_456_789i32, 4i32, 1i32)).unwrap();
+        write_key(&mut data, 4, 0x44);
+        data.write_val(&0i32).unwrap();
+
+        let auth = MtpAuthorization::from_serialized(&data, Location::default()).unwrap();
+        assert_eq!(auth.user_id, UserId::new(123_456_789));
+        assert_eq!(auth.main_dc_id, 4);
+        assert_eq!(auth.main_key().unwrap().data(), &[0x44; 256]);
+        assert!(auth.keys_to_destroy.is_empty());
+    }
+}
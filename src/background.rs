@@ -0,0 +1,350 @@
+use std::borrow::Cow;
+use std::convert::TryInto;
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use crate::data::UserId;
+use crate::descriptor::{EncryptedDescriptor, ValueStream};
+use crate::error::{Error, Location, Result};
+use crate::legacy_media::MediaType;
+
+/// `Data::WallPaper::kVersionTag`, which takes the place of the legacy 32-bit owner id.
+const WALL_PAPER_VERSION_TAG: i32 = 0x7FFF_FFFF;
+/// Marks a legacy background file that stores a full wallpaper rather than just a builtin id.
+const LEGACY_BACKGROUND_ID: i32 = -111;
+/// The legacy id of the wallpaper that comes with the current theme.
+const THEME_BACKGROUND_ID: i32 = -2;
+/// `SerializeMaybeColor` writes this for a color slot that isn't set.
+const NO_COLOR: u32 = u32::MAX;
+
+/// A wallpaper, as `Data::WallPaper::serialize` stores it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WallPaper {
+    /// Builtin and local wallpapers have an id made from a legacy 32-bit one, see [`legacy_id`](Self::legacy_id).
+    pub id: u64,
+    pub access_hash: u64,
+    pub owner_id: UserId,
+    /// A combination of the `WallPaperFlag`s, such as [`PATTERN`](Self::PATTERN).
+    pub flags: u32,
+    pub slug: String,
+    /// `WallPaperSettings`, for whether the image is blurred or moves with the window.
+    pub settings: u32,
+    /// Up to four colors as `0xRRGGBB`, where more than one makes a gradient.
+    pub background_colors: Vec<u32>,
+    /// How visible a pattern is over its colors, in percent; negative for dark patterns.
+    pub intensity: i32,
+    /// The angle of the gradient, in degrees.
+    pub rotation: i32,
+}
+
+impl WallPaper {
+    pub const CREATOR: u32 = 1 << 0;
+    pub const DEFAULT: u32 = 1 << 1;
+    pub const PATTERN: u32 = 1 << 2;
+    pub const DARK: u32 = 1 << 3;
+
+    /// The `Window::Theme` legacy background id for a builtin or local wallpaper.
+    pub fn legacy_id(&self) -> Option<i32> {
+        if self.id >> 32 == 0xFFFF_FFFF {
+            Some(self.id as i32)
+        } else {
+            None
+        }
+    }
+
+    /// Whether this stands for the current theme's own background, rather than an image of its own.
+    pub fn is_theme(&self) -> bool {
+        self.legacy_id() == Some(THEME_BACKGROUND_ID)
+    }
+
+    pub fn is_pattern(&self) -> bool {
+        self.flags & Self::PATTERN != 0
+    }
+
+    fn from_legacy_id(legacy_id: i32) -> u64 {
+        0xFFFF_FFFF_0000_0000 | u64::from(legacy_id as u32)
+    }
+
+    fn read(mut stream: impl Read) -> std::io::Result<Self> {
+        let mut result = Self {
+            id: stream.read_val()?,
+            access_hash: stream.read_val()?,
+            ..Default::default()
+        };
+        let version_tag = stream.read_val::<i32>()?;
+        let colors;
+        if version_tag == WALL_PAPER_VERSION_TAG {
+            let _version = stream.read_val::<i32>()?;
+            result.flags = stream.read_val()?;
+            result.slug = stream.read_val()?;
+            result.settings = stream.read_val()?;
+            colors = stream.read_val::<(u32, u32, u32, u32)>()?;
+            result.intensity = stream.read_val()?;
+            result.rotation = stream.read_val()?;
+            result.owner_id = UserId::new(stream.read_val()?);
+        } else {
+            result.owner_id = UserId::new(u64::from(version_tag as u32));
+            result.flags = stream.read_val()?;
+            result.slug = stream.read_val()?;
+            result.settings = stream.read_val()?;
+            colors = (stream.read_val()?, NO_COLOR, NO_COLOR, NO_COLOR);
+            result.intensity = stream.read_val()?;
+        }
+        result.background_colors = [colors.0, colors.1, colors.2, colors.3]
+            .iter()
+            .copied()
+            .take_while(|&c| c != NO_COLOR)
+            .collect();
+        Ok(result)
+    }
+}
+
+/// The image tdesktop saved for a wallpaper.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum BackgroundImage {
+    /// Wallpapers that are plain colors or builtin have no image.
+    #[default]
+    None,
+    /// `QImage::Format_ARGB32_Premultiplied` pixels, as `writeBackground` stores them.
+    Raw {
+        width: u32,
+        height: u32,
+        /// One native-endian `0xAARRGGBB` word per pixel, row by row.
+        pixels: Vec<u8>,
+    },
+    /// An encoded file, as legacy background files and the theme cache hold.
+    Encoded(Vec<u8>),
+}
+
+impl BackgroundImage {
+    pub fn is_none(&self) -> bool {
+        matches!(self, Self::None)
+    }
+
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        match self {
+            Self::None => None,
+            Self::Raw { width, height, .. } => Some((*width, *height)),
+            Self::Encoded(data) => image_dimensions(data),
+        }
+    }
+
+    /// The format of the file [`to_file`](Self::to_file) produces.
+    pub fn media_type(&self) -> MediaType {
+        match self {
+            Self::None => MediaType::Unknown,
+            Self::Raw { .. } => MediaType::Png,
+            Self::Encoded(data) => MediaType::detect(data),
+        }
+    }
+
+    /// The image as a file, encoding raw pixels as a PNG and passing encoded files through.
+    pub fn to_file(&self) -> std::io::Result<Option<Cow<'_, [u8]>>> {
+        match self {
+            Self::None => Ok(None),
+            Self::Raw {
+                width,
+                height,
+                pixels,
+            } => encode_png(*width, *height, pixels).map(|png| Some(Cow::Owned(png))),
+            Self::Encoded(data) => Ok(Some(Cow::Borrowed(data))),
+        }
+    }
+
+    /// Parses the blob `writeBackground` stores: a width and a height, then the pixels.
+    fn from_raw(data: Vec<u8>) -> Option<Self> {
+        if data.is_empty() {
+            return Some(Self::None);
+        }
+        let header = data.get(..RAW_IMAGE_HEADER_SIZE)?;
+        let width = i32::from_ne_bytes(header[0..4].try_into().unwrap());
+        let height = i32::from_ne_bytes(header[4..8].try_into().unwrap());
+        if width <= 0 || height <= 0 {
+            return None;
+        }
+        let (width, height) = (width as u32, height as u32);
+        let size = (width as usize)
+            .checked_mul(height as usize)?
+            .checked_mul(4)?;
+        if data.len() - RAW_IMAGE_HEADER_SIZE != size {
+            return None;
+        }
+        Some(Self::Raw {
+            width,
+            height,
+            pixels: data[RAW_IMAGE_HEADER_SIZE..].to_vec(),
+        })
+    }
+}
+
+/// `writeBackground` puts the image's width and height, as native-endian `qint32`s, before its pixels.
+const RAW_IMAGE_HEADER_SIZE: usize = 8;
+
+/// Encodes premultiplied ARGB32 pixels as an RGBA PNG.
+fn encode_png(width: u32, height: u32, pixels: &[u8]) -> std::io::Result<Vec<u8>> {
+    let unpremultiply = |c: u32, a: u32| ((c * 255 + a / 2) / a).min(255) as u8;
+    let mut rgba = Vec::with_capacity(pixels.len());
+    for chunk in pixels.chunks_exact(4) {
+        let argb = u32::from_ne_bytes(chunk.try_into().unwrap());
+        let a = argb >> 24;
+        if a == 0 {
+            rgba.extend_from_slice(&[0; 4]);
+            continue;
+        }
+        let (r, g, b) = ((argb >> 16) & 0xFF, (argb >> 8) & 0xFF, argb & 0xFF);
+        rgba.extend_from_slice(&[
+            unpremultiply(r, a),
+            unpremultiply(g, a),
+            unpremultiply(b, a),
+            a as u8,
+        ]);
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&rgba)?;
+    writer.finish()?;
+    Ok(png)
+}
+
+/// A wallpaper along with the image tdesktop saved for it.
+#[derive(Debug, Clone, Default)]
+pub struct Background {
+    pub paper: WallPaper,
+    pub image: BackgroundImage,
+}
+
+impl Background {
+    /// The format [`export_image`](Self::export_image) writes.
+    pub fn image_type(&self) -> MediaType {
+        self.image.media_type()
+    }
+
+    /// Writes the image to `path`, which should be given the extension of [`image_type`](Self::image_type).
+    ///
+    /// Returns `false`, writing nothing, if the wallpaper has no image.
+    pub fn export_image(&self, path: impl AsRef<Path>) -> std::io::Result<bool> {
+        match self.image.to_file()? {
+            Some(file) => std::fs::write(path, file).map(|()| true),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Where the background the user sees comes from.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BackgroundSource {
+    /// The theme's cached background.
+    Theme,
+    /// The file named by the `BackgroundKey` setting.
+    File,
+}
+
+/// The background tdesktop would draw in the active day or night mode.
+#[derive(Debug, Clone)]
+pub struct ThemeBackground {
+    pub source: BackgroundSource,
+    pub tiled: bool,
+    /// The wallpaper, when the background came from a file.
+    pub paper: Option<WallPaper>,
+    pub image: BackgroundImage,
+    /// The width and height of `image`, if they could be read.
+    pub dimensions: Option<(u32, u32)>,
+}
+
+impl ThemeBackground {
+    pub fn image_type(&self) -> MediaType {
+        self.image.media_type()
+    }
+
+    pub(crate) fn new(
+        source: BackgroundSource,
+        tiled: bool,
+        paper: Option<WallPaper>,
+        image: BackgroundImage,
+    ) -> Self {
+        Self {
+            source,
+            tiled,
+            paper,
+            dimensions: image.dimensions(),
+            image,
+        }
+    }
+}
+
+/// Reads the width and height out of a PNG or JPEG header.
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be_u16 = |at: usize| Some(u16::from_be_bytes([*data.get(at)?, *data.get(at + 1)?]));
+    let be_u32 = |at: usize| Some(u32::from_be_bytes(data.get(at..at + 4)?.try_into().ok()?));
+
+    match MediaType::detect(data) {
+        // the IHDR chunk always comes first
+        MediaType::Png => Some((be_u32(16)?, be_u32(20)?)),
+        MediaType::Jpeg => {
+            let mut at = 2;
+            loop {
+                if *data.get(at)? != 0xFF {
+                    return None;
+                }
+                let marker = *data.get(at + 1)?;
+                let is_frame =
+                    matches!(marker, 0xC0..=0xCF) && !matches!(marker, 0xC4 | 0xC8 | 0xCC);
+                if is_frame {
+                    let height = be_u16(at + 5)?;
+                    let width = be_u16(at + 7)?;
+                    return Some((u32::from(width), u32::from(height)));
+                }
+                at += 2 + usize::from(be_u16(at + 2)?);
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Reads a decrypted background file, as named by the `BackgroundKey` setting.
+pub(crate) fn read_background(stream: &mut EncryptedDescriptor) -> Result<Background> {
+    let location = stream.location();
+    let serialized = stream.read_bytes()?;
+    let mut blob = Cursor::new(&serialized[..]);
+    let paper = WallPaper::read(&mut blob).map_err(|e| {
+        // skip over the blob's length prefix
+        let offset = location.offset + 4 + blob.position();
+        Error::from_io(e, Location { offset, ..location })
+    })?;
+
+    let location = stream.location();
+    let image = BackgroundImage::from_raw(stream.read_bytes()?)
+        .ok_or_else(|| Error::invalid_data(location, "bad background image size"))?;
+    Ok(Background { paper, image })
+}
+
+/// Reads a decrypted legacy background file, from an account's `BackgroundOld` map entry.
+pub(crate) fn read_legacy_background(stream: &mut EncryptedDescriptor) -> Result<Background> {
+    let legacy_id = stream.read_val::<i32>()?;
+    let paper = if legacy_id == LEGACY_BACKGROUND_ID {
+        WallPaper {
+            id: stream.read_val()?,
+            access_hash: stream.read_val()?,
+            flags: stream.read_val()?,
+            slug: stream.read_val()?,
+            ..Default::default()
+        }
+    } else {
+        WallPaper {
+            id: WallPaper::from_legacy_id(legacy_id),
+            ..Default::default()
+        }
+    };
+
+    let image = stream.read_bytes()?;
+    let image = if image.is_empty() {
+        BackgroundImage::None
+    } else {
+        BackgroundImage::Encoded(image)
+    };
+    Ok(Background { paper, image })
+}
@@ -0,0 +1,276 @@
+use std::io::Read;
+
+use crate::descriptor::{at_end, parse_blob, read_bool, read_list, Blob, ValueStream};
+use crate::error::{Location, Result};
+
+/// The application-wide settings tdesktop keeps in `Core::Settings`.
+///
+/// Newer versions of tdesktop append fields to the end of the serialized data, so every field
+/// is optional, and is `None` if the version that wrote the settings predates it.
+/// Anything past the last field known here is kept in `trailing`.
+#[derive(Debug, Default)]
+pub struct CoreSettings {
+    /// The version of Telegram Desktop that wrote the settings file.
+    pub app_version: i32,
+
+    pub themes_accent_colors: Vec<u8>,
+    pub adaptive_for_wide: Option<bool>,
+    pub moderate_mode_enabled: Option<bool>,
+    /// Stored as millionths.
+    pub song_volume: Option<i32>,
+    /// Stored as millionths.
+    pub video_volume: Option<i32>,
+    pub ask_download_path: Option<bool>,
+    pub download_path: Option<String>,
+    pub download_path_bookmark: Option<Vec<u8>>,
+    pub non_default_voice_playback_speed: Option<bool>,
+    pub sound_notify: Option<bool>,
+    pub desktop_notify: Option<bool>,
+    pub flash_bounce_notify: Option<bool>,
+    pub notify_view: Option<i32>,
+    /// 0 for the platform default, 1 if enabled and 2 if disabled.
+    pub native_notifications: Option<i32>,
+    pub notifications_count: Option<i32>,
+    pub notifications_corner: Option<i32>,
+    pub auto_lock: Option<i32>,
+    pub call_output_device_id: Option<String>,
+    pub call_input_device_id: Option<String>,
+    pub call_output_volume: Option<i32>,
+    pub call_input_volume: Option<i32>,
+    pub call_audio_ducking_enabled: Option<bool>,
+    pub last_seen_warning_seen: Option<bool>,
+    pub sound_overrides: Option<Vec<(String, String)>>,
+    pub send_files_way: Option<i32>,
+    pub send_submit_way: Option<i32>,
+    pub include_muted_counter: Option<bool>,
+    pub count_unread_messages: Option<bool>,
+    pub exe_launch_warning: Option<bool>,
+    pub notify_about_pinned: Option<bool>,
+    pub loop_animated_stickers: Option<bool>,
+    pub large_emoji: Option<bool>,
+    pub replace_emoji: Option<bool>,
+    pub suggest_emoji: Option<bool>,
+    pub suggest_stickers_by_emoji: Option<bool>,
+    pub spellchecker_enabled: Option<bool>,
+    pub video_playback_speed: Option<i32>,
+    pub video_pip_geometry: Option<Vec<u8>>,
+    pub dictionaries_enabled: Option<Vec<i64>>,
+    pub auto_download_dictionaries: Option<bool>,
+    pub main_menu_accounts_shown: Option<bool>,
+    pub tabbed_selector_section_enabled: Option<bool>,
+    pub float_player_column: Option<i32>,
+    pub float_player_corner: Option<i32>,
+    pub third_section_info_enabled: Option<bool>,
+    /// Stored as millionths.
+    pub dialogs_width_ratio: Option<i32>,
+    pub third_column_width: Option<i32>,
+    pub third_section_extended_by: Option<i32>,
+    pub notify_from_all: Option<bool>,
+    pub native_window_frame: Option<bool>,
+    pub system_dark_mode_enabled: Option<bool>,
+    pub camera_device_id: Option<String>,
+    pub ip_reveal_warning: Option<bool>,
+    pub group_call_push_to_talk: Option<bool>,
+    pub group_call_push_to_talk_shortcut: Option<Vec<u8>>,
+    pub group_call_push_to_talk_delay: Option<i64>,
+    pub call_audio_backend: Option<i32>,
+    pub disable_calls: Option<bool>,
+    pub window_position: Option<WindowPosition>,
+    pub recent_emoji: Option<Vec<(String, u16)>>,
+    pub emoji_variants: Option<Vec<(String, u8)>>,
+    pub disable_opengl: Option<bool>,
+    pub group_call_noise_suppression: Option<bool>,
+    pub work_mode: Option<i32>,
+    pub proxy: Option<ProxySettings>,
+    pub hidden_group_call_tooltips: Option<i32>,
+    pub photo_editor_brush: Option<Vec<u8>>,
+
+    /// Fields written by newer versions than this reader knows about.
+    pub trailing: Vec<u8>,
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct WindowPosition {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+    pub moncrc: i32,
+    pub maximized: bool,
+    /// Missing from older settings.
+    pub scale: Option<i32>,
+}
+
+#[derive(Debug, Default)]
+pub struct ProxySettings {
+    pub try_ipv6: bool,
+    pub use_proxy_for_calls: bool,
+    /// 0 for the system proxy, 1 if enabled and 2 if disabled.
+    pub settings: i32,
+    pub selected: ProxyData,
+    pub list: Vec<ProxyData>,
+}
+
+#[derive(Debug, Default)]
+pub struct ProxyData {
+    /// 0 for none, 1 for SOCKS5, 2 for HTTP and 3 for MTProto.
+    pub kind: i32,
+    pub host: String,
+    pub port: i32,
+    pub user: String,
+    pub password: String,
+}
+
+impl ProxyData {
+    fn from_serialized(serialized: &[u8]) -> std::io::Result<Self> {
+        let mut stream = serialized;
+        if stream.is_empty() {
+            return Ok(Self::default());
+        }
+        Ok(Self {
+            kind: stream.read_val()?,
+            host: stream.read_val()?,
+            port: stream.read_val()?,
+            user: stream.read_val()?,
+            password: stream.read_val()?,
+        })
+    }
+}
+
+impl ProxySettings {
+    fn from_serialized(serialized: &[u8]) -> std::io::Result<Self> {
+        let mut stream = serialized;
+        Ok(Self {
+            try_ipv6: read_bool(&mut stream)?,
+            use_proxy_for_calls: read_bool(&mut stream)?,
+            settings: stream.read_val()?,
+            selected: ProxyData::from_serialized(&stream.read_bytes()?)?,
+            list: read_list(&mut stream, |s| {
+                ProxyData::from_serialized(&s.read_bytes()?)
+            })?,
+        })
+    }
+}
+
+impl WindowPosition {
+    fn from_serialized(serialized: &[u8]) -> std::io::Result<Self> {
+        let mut stream = serialized;
+        Ok(Self {
+            x: stream.read_val()?,
+            y: stream.read_val()?,
+            w: stream.read_val()?,
+            h: stream.read_val()?,
+            moncrc: stream.read_val()?,
+            maximized: read_bool(&mut stream)?,
+            scale: if stream.is_empty() {
+                None
+            } else {
+                Some(stream.read_val()?)
+            },
+        })
+    }
+}
+
+impl CoreSettings {
+    /// Parses the blob stored in an `ApplicationSettings` setting, which was read from `location`.
+    pub(crate) fn from_serialized(
+        serialized: &[u8],
+        app_version: i32,
+        location: Location,
+    ) -> Result<Self> {
+        parse_blob(serialized, location, |stream| {
+            let mut result = Self {
+                app_version,
+                themes_accent_colors: stream.read_bytes()?,
+                ..Default::default()
+            };
+            result.read_fields(stream)?;
+            stream.read_to_end(&mut result.trailing)?;
+            Ok(result)
+        })
+    }
+
+    fn read_fields(&mut self, stream: &mut Blob<'_>) -> std::io::Result<()> {
+        macro_rules! fields {
+            ($($field:ident: $read:expr,)*) => {
+                $(
+                    if at_end(stream) {
+                        return Ok(());
+                    }
+                    self.$field = Some($read);
+                )*
+            };
+        }
+
+        fields! {
+            adaptive_for_wide: read_bool(&mut *stream)?,
+            moderate_mode_enabled: read_bool(&mut *stream)?,
+            song_volume: stream.read_val()?,
+            video_volume: stream.read_val()?,
+            ask_download_path: read_bool(&mut *stream)?,
+            download_path: stream.read_val()?,
+            download_path_bookmark: stream.read_bytes()?,
+            non_default_voice_playback_speed: read_bool(&mut *stream)?,
+            sound_notify: read_bool(&mut *stream)?,
+            desktop_notify: read_bool(&mut *stream)?,
+            flash_bounce_notify: read_bool(&mut *stream)?,
+            notify_view: stream.read_val()?,
+            native_notifications: stream.read_val()?,
+            notifications_count: stream.read_val()?,
+            notifications_corner: stream.read_val()?,
+            auto_lock: stream.read_val()?,
+            call_output_device_id: stream.read_val()?,
+            call_input_device_id: stream.read_val()?,
+            call_output_volume: stream.read_val()?,
+            call_input_volume: stream.read_val()?,
+            call_audio_ducking_enabled: read_bool(&mut *stream)?,
+            last_seen_warning_seen: read_bool(&mut *stream)?,
+            sound_overrides: read_list(stream, |s| s.read_val())?,
+            send_files_way: stream.read_val()?,
+            send_submit_way: stream.read_val()?,
+            include_muted_counter: read_bool(&mut *stream)?,
+            count_unread_messages: read_bool(&mut *stream)?,
+            exe_launch_warning: read_bool(&mut *stream)?,
+            notify_about_pinned: read_bool(&mut *stream)?,
+            loop_animated_stickers: read_bool(&mut *stream)?,
+            large_emoji: read_bool(&mut *stream)?,
+            replace_emoji: read_bool(&mut *stream)?,
+            suggest_emoji: read_bool(&mut *stream)?,
+            suggest_stickers_by_emoji: read_bool(&mut *stream)?,
+            spellchecker_enabled: read_bool(&mut *stream)?,
+            video_playback_speed: stream.read_val()?,
+            video_pip_geometry: stream.read_bytes()?,
+            dictionaries_enabled: read_list(stream, |s| s.read_val())?,
+            auto_download_dictionaries: read_bool(&mut *stream)?,
+            main_menu_accounts_shown: read_bool(&mut *stream)?,
+            tabbed_selector_section_enabled: read_bool(&mut *stream)?,
+            float_player_column: stream.read_val()?,
+            float_player_corner: stream.read_val()?,
+            third_section_info_enabled: read_bool(&mut *stream)?,
+            dialogs_width_ratio: stream.read_val()?,
+            third_column_width: stream.read_val()?,
+            third_section_extended_by: stream.read_val()?,
+            notify_from_all: read_bool(&mut *stream)?,
+            native_window_frame: read_bool(&mut *stream)?,
+            system_dark_mode_enabled: read_bool(&mut *stream)?,
+            camera_device_id: stream.read_val()?,
+            ip_reveal_warning: read_bool(&mut *stream)?,
+            group_call_push_to_talk: read_bool(&mut *stream)?,
+            group_call_push_to_talk_shortcut: stream.read_bytes()?,
+            group_call_push_to_talk_delay: stream.read_val()?,
+            call_audio_backend: stream.read_val()?,
+            disable_calls: read_bool(&mut *stream)?,
+            window_position: WindowPosition::from_serialized(&stream.read_bytes()?)?,
+            recent_emoji: read_list(stream, |s| s.read_val())?,
+            emoji_variants: read_list(stream, |s| s.read_val())?,
+            disable_opengl: read_bool(&mut *stream)?,
+            group_call_noise_suppression: read_bool(&mut *stream)?,
+            work_mode: stream.read_val()?,
+            proxy: ProxySettings::from_serialized(&stream.read_bytes()?)?,
+            hidden_group_call_tooltips: stream.read_val()?,
+            photo_editor_brush: stream.read_bytes()?,
+        }
+
+        Ok(())
+    }
+}
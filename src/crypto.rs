@@ -22,6 +22,17 @@ impl MtpAuthKey {
     const LOCAL_ENCRYPT_ITER_COUNT: u32 = 4000;
     const LOCAL_ENCRYPT_NO_PWD_ITER_COUNT: u32 = 4;
 
+    /// The raw 256-byte key.
+    pub fn data(&self) -> &[u8; Self::K_SIZE] {
+        &self.data
+    }
+
+    /// The key id MTProto identifies this key by: the low 64 bits of its SHA1.
+    pub fn key_id(&self) -> u64 {
+        let hash = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &self.data);
+        u64::from_le_bytes(hash.as_ref()[12..20].try_into().unwrap())
+    }
+
     pub fn create_local(passcode: &[u8], salt: &[u8; LOCAL_ENCRYPT_SALT_SIZE]) -> Rc<Self> {
         let mut key = Self::BLANK;
         let hash = {
@@ -104,6 +115,15 @@ impl MtpAuthKey {
     }
 }
 
+impl std::fmt::Debug for MtpAuthKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // never print the key itself
+        f.debug_struct("MtpAuthKey")
+            .field("key_id", &format_args!("{:016x}", self.key_id()))
+            .finish()
+    }
+}
+
 impl Readable for Rc<MtpAuthKey> {
     fn read_from(mut stream: impl Read) -> std::io::Result<Self> {
         let mut key = MtpAuthKey::BLANK;
@@ -112,6 +132,13 @@ impl Readable for Rc<MtpAuthKey> {
     }
 }
 
+// NOTE: tdesktop prepares local keys with `send = false` in both directions.
+pub fn aes_encrypt_local(src: &[u8], key: &MtpAuthKey, key128: &[u8; 16]) -> Vec<u8> {
+    let (mut aes_key, mut aes_iv) = ([0; 32], [0; 32]);
+    key.prepare_aes_oldmtp(key128, &mut aes_key, &mut aes_iv, false);
+    aes::ige_encrypt(src, &aes_key, &aes_iv)
+}
+
 pub fn aes_decrypt_local(src: &[u8], key: &MtpAuthKey, key128: &[u8; 16]) -> Vec<u8> {
     let (mut aes_key, mut aes_iv) = ([0; 32], [0; 32]);
     key.prepare_aes_oldmtp(key128, &mut aes_key, &mut aes_iv, false);
@@ -0,0 +1,102 @@
+use serde::Serialize;
+use std::io::Read;
+
+use crate::descriptor::{Readable, ValueStream};
+
+pub type BareId = u64;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct ChatIdType<const SHIFT: u8> {
+    pub bare: BareId,
+}
+
+impl<const SHIFT: u8> ChatIdType<SHIFT> {
+    pub const fn new(bare: BareId) -> Self {
+        Self { bare }
+    }
+}
+
+pub type UserId = ChatIdType<0>;
+pub type ChatId = ChatIdType<1>;
+pub type ChannelId = ChatIdType<2>;
+pub type FakeChatId = ChatIdType<0x7F>;
+
+pub type DocumentId = u64;
+pub type MsgId = i64;
+
+/// A user, chat or channel id, tagged with its kind in the bits above the bare id.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct PeerId(pub u64);
+
+impl PeerId {
+    const KIND_SHIFT: u32 = 48;
+    const BARE_MASK: u64 = (1 << Self::KIND_SHIFT) - 1;
+
+    // bit 7 of the kind is reserved, and set in every peer id that isn't serialized the legacy way
+    const SERIALIZED_FLAG: u64 = 0x80 << Self::KIND_SHIFT;
+
+    const LEGACY_BARE_MASK: u64 = 0x0_FFFF_FFFF;
+    const LEGACY_KIND_MASK: u64 = 0xF_0000_0000;
+    const LEGACY_USER: u64 = 0x0_0000_0000;
+    const LEGACY_CHAT: u64 = 0x1_0000_0000;
+    const LEGACY_CHANNEL: u64 = 0x2_0000_0000;
+    const LEGACY_FAKE: u64 = 0xF_0000_0000;
+
+    pub const fn from_chat_id<const SHIFT: u8>(id: ChatIdType<SHIFT>) -> Self {
+        Self(id.bare | ((SHIFT as u64) << Self::KIND_SHIFT))
+    }
+
+    /// Decodes a peer id the way tdesktop's `DeserializePeerId` does, including the 32-bit legacy layout.
+    pub fn from_serialized(serialized: u64) -> Self {
+        if serialized & Self::SERIALIZED_FLAG != 0 {
+            return Self(serialized & !Self::SERIALIZED_FLAG);
+        }
+
+        let bare = serialized & Self::LEGACY_BARE_MASK;
+        match serialized & Self::LEGACY_KIND_MASK {
+            Self::LEGACY_USER => Self::from_chat_id(UserId::new(bare)),
+            Self::LEGACY_CHAT => Self::from_chat_id(ChatId::new(bare)),
+            Self::LEGACY_CHANNEL => Self::from_chat_id(ChannelId::new(bare)),
+            Self::LEGACY_FAKE => Self::from_chat_id(FakeChatId::new(bare)),
+            _ => Self(0),
+        }
+    }
+
+    pub fn serialize(self) -> u64 {
+        self.0 | Self::SERIALIZED_FLAG
+    }
+
+    fn kind(self) -> u8 {
+        (self.0 >> Self::KIND_SHIFT) as u8
+    }
+
+    fn bare(self) -> BareId {
+        self.0 & Self::BARE_MASK
+    }
+
+    fn to_chat_id<const SHIFT: u8>(self) -> Option<ChatIdType<SHIFT>> {
+        if self.0 != 0 && self.kind() == SHIFT {
+            Some(ChatIdType::new(self.bare()))
+        } else {
+            None
+        }
+    }
+
+    pub fn to_user(self) -> Option<UserId> {
+        self.to_chat_id()
+    }
+
+    pub fn to_chat(self) -> Option<ChatId> {
+        self.to_chat_id()
+    }
+
+    pub fn to_channel(self) -> Option<ChannelId> {
+        self.to_chat_id()
+    }
+}
+
+impl Readable for PeerId {
+    fn read_from(mut stream: impl Read) -> std::io::Result<Self> {
+        stream.read_val::<u64>().map(Self::from_serialized)
+    }
+}
@@ -1,59 +1,109 @@
-use anyhow::{bail, ensure, Result};
-use byteorder::{ReadBytesExt, BE, LE};
+use byteorder::{ReadBytesExt, WriteBytesExt, BE, LE};
 use ring::digest;
+use ring::rand::{SecureRandom, SystemRandom};
 use std::convert::TryInto;
-use std::ffi::OsStr;
-use std::fs::File;
-use std::io::{Cursor, Read};
+use std::fs::{self, File};
+use std::io::{Cursor, Read, Write};
 use std::path::{Path, PathBuf};
 
-use super::{aes_decrypt_local, MtpAuthKey};
+use super::{aes_decrypt_local, aes_encrypt_local, MtpAuthKey};
+use crate::error::{Error, Location, Result};
 
 const TDF_MAGIC: [u8; 4] = *b"TDF$";
+const TDF_HEADER_SIZE: u64 = (TDF_MAGIC.len() + std::mem::size_of::<i32>()) as u64;
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut s = path.to_owned().into_os_string();
+    s.push(suffix);
+    PathBuf::from(s)
+}
+
+fn file_location(name: &Path, suffix: &str) -> Location {
+    Location {
+        file: name
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default(),
+        path: with_suffix(name, suffix),
+        offset: 0,
+    }
+}
 
 pub struct FileReadDescriptor {
     version: i32,
     data: Cursor<Vec<u8>>,
+    location: Location,
+    rejected: Vec<Error>,
 }
 
 impl FileReadDescriptor {
-    pub fn open(name: impl AsRef<OsStr>, base_path: impl AsRef<Path>) -> Result<Self> {
-        let path = base_path.as_ref().join(name.as_ref());
+    /// Opens the file `name` the way tdesktop does, trying each of the `s`, `1` and `0` variants.
+    ///
+    /// Every variant that exists is checked, and the valid one written by the newest version wins.
+    /// Variants that were found to be invalid are kept as [`rejected`](Self::rejected).
+    /// `name` is relative to `base_path`, which should be the tdata directory.
+    pub fn open(name: impl AsRef<Path>, base_path: impl AsRef<Path>) -> Result<Self> {
+        let name = name.as_ref();
+        let path = base_path.as_ref().join(name);
+
+        let mut best: Option<Self> = None;
+        let mut rejected = Vec::new();
+
+        for suffix in ["s", "1", "0"] {
+            let candidate = with_suffix(&path, suffix);
+            if !candidate.exists() {
+                continue;
+            }
+
+            let location = file_location(name, suffix);
+            match Self::read_file(&candidate, location) {
+                Ok(file) => match &best {
+                    Some(b) if b.version >= file.version => {}
+                    _ => best = Some(file),
+                },
+                Err(e) => rejected.push(e),
+            }
+        }
 
-        let modern = {
-            let mut s = path.into_os_string();
-            s.push("s");
-            PathBuf::from(s)
-        };
+        let location = file_location(name, "");
+        match best {
+            Some(mut file) => {
+                file.rejected = rejected;
+                Ok(file)
+            }
+            None if rejected.is_empty() => Err(Error::NotFound { location }),
+            None => Err(Error::NoValidFile { location, rejected }),
+        }
+    }
 
-        let mut f = if modern.exists() {
-            File::open(modern)?
-        } else {
-            // NOTE: tdesktop tries all possible files.
-            // if one is invalid, it tries the next.
-            unimplemented!("modern files only")
-        };
+    fn read_file(path: &Path, location: Location) -> Result<Self> {
+        let io_error = |e| Error::from_io(e, location.clone());
+
+        let mut f = File::open(path).map_err(io_error)?;
 
         let mut magic = [0; TDF_MAGIC.len()];
-        f.read_exact(&mut magic)?;
+        f.read_exact(&mut magic).map_err(io_error)?;
         if magic != TDF_MAGIC {
-            bail!("bad magic");
+            return Err(Error::BadMagic { location });
         }
 
-        let version = f.read_i32::<LE>()?;
+        let version = f.read_i32::<LE>().map_err(io_error)?;
 
         let mut bytes = Vec::new();
-        f.read_to_end(&mut bytes)?;
+        f.read_to_end(&mut bytes).map_err(io_error)?;
+        if bytes.len() < 16 {
+            return Err(Error::UnexpectedEof { location });
+        }
         let data_size = bytes.len() - 16;
 
         let mut md5 = md5::Context::new();
         md5.consume(&bytes[..data_size]);
-        md5.consume(&(data_size as i32).to_le_bytes());
-        md5.consume(&version.to_le_bytes());
-        md5.consume(&magic);
+        md5.consume((data_size as i32).to_le_bytes());
+        md5.consume(version.to_le_bytes());
+        md5.consume(magic);
 
-        if md5.compute().0 != &bytes[data_size..] {
-            bail!("signature mismatch");
+        if md5.compute().0 != bytes[data_size..] {
+            return Err(Error::SignatureMismatch { location });
         }
 
         bytes.truncate(data_size);
@@ -61,13 +111,33 @@ impl FileReadDescriptor {
         Ok(Self {
             version,
             data: Cursor::new(bytes),
+            location,
+            rejected: Vec::new(),
         })
     }
 
-    #[allow(dead_code)]
     pub fn version(&self) -> i32 {
         self.version
     }
+
+    /// The errors that ruled out the other variants of the file, such as a corrupt `s` variant.
+    pub fn rejected(&self) -> &[Error] {
+        &self.rejected
+    }
+
+    pub fn location(&self) -> Location {
+        Location {
+            offset: TDF_HEADER_SIZE + self.data.position(),
+            ..self.location.clone()
+        }
+    }
+
+    /// Reads a byte array and decrypts it as an [`EncryptedDescriptor`].
+    pub fn read_encrypted(&mut self, key: &MtpAuthKey) -> Result<EncryptedDescriptor> {
+        let location = self.location();
+        let encrypted = self.read_bytes()?;
+        EncryptedDescriptor::decrypt_local(&encrypted, key, location)
+    }
 }
 
 impl Read for FileReadDescriptor {
@@ -76,14 +146,143 @@ impl Read for FileReadDescriptor {
     }
 }
 
+/// The writing counterpart to [`FileReadDescriptor`].
+///
+/// Values are buffered in memory, and only hit the disk once [`finish`](Self::finish) is called.
+pub struct FileWriteDescriptor {
+    name: PathBuf,
+    path: PathBuf,
+    version: i32,
+    data: Vec<u8>,
+}
+
+impl FileWriteDescriptor {
+    pub fn new(name: impl AsRef<Path>, base_path: impl AsRef<Path>, version: i32) -> Self {
+        let name = name.as_ref().to_owned();
+        Self {
+            path: base_path.as_ref().join(&name),
+            name,
+            version,
+            data: Vec::new(),
+        }
+    }
+
+    /// Signs the buffered data and atomically replaces the `s` variant of the file with it.
+    ///
+    /// Any legacy `0` or `1` variants are removed afterwards, so that they can't shadow the new file.
+    pub fn finish(self) -> Result<()> {
+        let location = file_location(&self.name, "s");
+        self.write_files().map_err(|e| Error::from_io(e, location))
+    }
+
+    fn write_files(&self) -> std::io::Result<()> {
+        let mut md5 = md5::Context::new();
+        md5.consume(&self.data);
+        md5.consume((self.data.len() as i32).to_le_bytes());
+        md5.consume(self.version.to_le_bytes());
+        md5.consume(TDF_MAGIC);
+
+        let modern = with_suffix(&self.path, "s");
+        let temp = with_suffix(&self.path, "s.tmp");
+
+        let write_temp = || -> std::io::Result<()> {
+            let mut f = File::create(&temp)?;
+            f.write_all(&TDF_MAGIC)?;
+            f.write_i32::<LE>(self.version)?;
+            f.write_all(&self.data)?;
+            f.write_all(&md5.compute().0)?;
+            f.sync_all()
+        };
+        if let Err(e) = write_temp().and_then(|()| fs::rename(&temp, &modern)) {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+
+        for legacy in [with_suffix(&self.path, "0"), with_suffix(&self.path, "1")] {
+            if legacy.exists() {
+                fs::remove_file(legacy)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Where the next value will be written, once the file is finished.
+    pub fn location(&self) -> Location {
+        Location {
+            offset: TDF_HEADER_SIZE + self.data.len() as u64,
+            ..file_location(&self.name, "s")
+        }
+    }
+
+    /// Encrypts `data` and writes it as a byte array, the counterpart to [`FileReadDescriptor::read_encrypted`].
+    pub fn write_encrypted(&mut self, data: EncryptedDescriptor, key: &MtpAuthKey) -> Result<()> {
+        let location = self.location();
+        let encrypted = data.encrypt_local(key);
+        self.write_bytes(&encrypted)
+            .map_err(|e| Error::from_io(e, location))
+    }
+}
+
+impl Write for FileWriteDescriptor {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 pub struct EncryptedDescriptor {
     data: Cursor<Vec<u8>>,
+    location: Location,
 }
 
 impl EncryptedDescriptor {
-    pub(crate) fn decrypt_local(encrypted: &[u8], key: &MtpAuthKey) -> Result<Self> {
+    const LENGTH_PREFIX: usize = std::mem::size_of::<u32>();
+
+    /// Creates an empty descriptor to be written to and then passed to [`encrypt_local`](Self::encrypt_local).
+    pub fn new() -> Self {
+        let mut data = Cursor::new(vec![0; Self::LENGTH_PREFIX]);
+        data.set_position(Self::LENGTH_PREFIX as u64);
+        Self {
+            data,
+            location: Location::default(),
+        }
+    }
+
+    /// Encrypts everything written so far, producing the same layout `decrypt_local` expects:
+    /// the first 16 bytes of the SHA1 of the plaintext, followed by the AES-IGE ciphertext.
+    pub fn encrypt_local(self, key: &MtpAuthKey) -> Vec<u8> {
+        let mut to_encrypt = self.data.into_inner();
+
+        let size = to_encrypt.len();
+        let full_size = (size + 0x0F) & !0x0F;
+        if full_size != size {
+            to_encrypt.resize(full_size, 0);
+            SystemRandom::new()
+                .fill(&mut to_encrypt[size..])
+                .expect("failed to generate padding");
+        }
+        to_encrypt[..Self::LENGTH_PREFIX].copy_from_slice(&(size as u32).to_le_bytes());
+
+        let sha = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &to_encrypt);
+        let key128: &[u8; 16] = sha.as_ref()[..16].try_into().unwrap();
+
+        let mut encrypted = Vec::with_capacity(16 + full_size);
+        encrypted.extend_from_slice(key128);
+        encrypted.extend(aes_encrypt_local(&to_encrypt, key, key128));
+        encrypted
+    }
+
+    /// Decrypts `encrypted`, which was read from `location`.
+    ///
+    /// Errors raised while reading the decrypted data will point into the same file,
+    /// with offsets relative to the start of the decrypted data.
+    pub fn decrypt_local(encrypted: &[u8], key: &MtpAuthKey, location: Location) -> Result<Self> {
         if encrypted.len() <= 16 || encrypted.len() & 0xF != 0 {
-            bail!("bad encrypted part size");
+            return Err(Error::BadEncryptedSize { location });
         }
         let full_len = encrypted.len() - 16;
 
@@ -93,21 +292,59 @@ impl EncryptedDescriptor {
 
         let sha = digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, &decrypted);
         if sha.as_ref()[..16] != encrypted_key[..] {
-            bail!("bad decrypt key");
+            return Err(Error::BadDecryptKey { location });
         }
 
-        const FOUR: usize = std::mem::size_of::<u32>();
+        const FOUR: usize = EncryptedDescriptor::LENGTH_PREFIX;
 
         let data_len = u32::from_le_bytes(decrypted[..4].try_into().unwrap()) as usize;
         if data_len > decrypted.len() || data_len <= full_len - 16 || data_len < FOUR {
-            bail!("bad decrypted part");
+            return Err(Error::BadDecryptedPart { location });
         }
 
         decrypted.truncate(data_len);
 
         let mut data = Cursor::new(decrypted);
         data.set_position(FOUR as u64);
-        Ok(Self { data })
+        Ok(Self { data, location })
+    }
+
+    pub fn location(&self) -> Location {
+        Location {
+            offset: self.data.position(),
+            ..self.location.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+impl EncryptedDescriptor {
+    /// Turns a descriptor that was written to into one that reads back what was written.
+    pub(crate) fn reopen(self) -> Self {
+        let mut data = self.data;
+        let len = data.get_ref().len() as u32;
+        data.get_mut()[..Self::LENGTH_PREFIX].copy_from_slice(&len.to_le_bytes());
+        data.set_position(Self::LENGTH_PREFIX as u64);
+        Self {
+            data,
+            location: Location::default(),
+        }
+    }
+}
+
+impl Default for EncryptedDescriptor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Write for EncryptedDescriptor {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.data.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
     }
 }
 
@@ -134,6 +371,11 @@ impl Readable for i64 {
         stream.read_i64::<BE>()
     }
 }
+impl Readable for u8 {
+    fn read_from(mut stream: impl Read) -> std::io::Result<Self> {
+        stream.read_u8()
+    }
+}
 impl Readable for u16 {
     fn read_from(mut stream: impl Read) -> std::io::Result<Self> {
         stream.read_u16::<BE>()
@@ -221,6 +463,117 @@ impl Readable for Bytes {
     }
 }
 
+pub trait Writable {
+    fn write_to(&self, stream: impl Write) -> std::io::Result<()>;
+}
+
+impl Writable for i32 {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        stream.write_i32::<BE>(*self)
+    }
+}
+impl Writable for i64 {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        stream.write_i64::<BE>(*self)
+    }
+}
+impl Writable for u16 {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        stream.write_u16::<BE>(*self)
+    }
+}
+impl Writable for u32 {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        stream.write_u32::<BE>(*self)
+    }
+}
+impl Writable for u64 {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        stream.write_u64::<BE>(*self)
+    }
+}
+impl<T: Writable> Writable for Vec<T> {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        (self.len() as u32).write_to(&mut stream)?;
+        for item in self {
+            item.write_to(&mut stream)?;
+        }
+        Ok(())
+    }
+}
+impl Writable for String {
+    fn write_to(&self, stream: impl Write) -> std::io::Result<()> {
+        let bytes = self
+            .encode_utf16()
+            .flat_map(u16::to_be_bytes)
+            .collect::<Vec<u8>>();
+        Bytes(bytes).write_to(stream)
+    }
+}
+impl<A: Writable, B: Writable> Writable for (A, B) {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        self.0.write_to(&mut stream)?;
+        self.1.write_to(&mut stream)
+    }
+}
+impl<A: Writable, B: Writable, C: Writable> Writable for (A, B, C) {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        self.0.write_to(&mut stream)?;
+        self.1.write_to(&mut stream)?;
+        self.2.write_to(&mut stream)
+    }
+}
+impl<A: Writable, B: Writable, C: Writable, D: Writable> Writable for (A, B, C, D) {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        self.0.write_to(&mut stream)?;
+        self.1.write_to(&mut stream)?;
+        self.2.write_to(&mut stream)?;
+        self.3.write_to(&mut stream)
+    }
+}
+impl Writable for Bytes {
+    fn write_to(&self, mut stream: impl Write) -> std::io::Result<()> {
+        stream.write_bytes(&self.0)
+    }
+}
+
+/// Tdesktop stores booleans as `qint32`s, where only 1 is true.
+pub(crate) fn read_bool(stream: impl Read) -> std::io::Result<bool> {
+    i32::read_from(stream).map(|v| v == 1)
+}
+
+/// Reads an `i32`-counted list, the way tdesktop serializes most of its containers.
+pub(crate) fn read_list<R: Read, T>(
+    stream: &mut R,
+    mut read: impl FnMut(&mut R) -> std::io::Result<T>,
+) -> std::io::Result<Vec<T>> {
+    let count = i32::read_from(&mut *stream)?.max(0);
+    (0..count).map(|_| read(stream)).collect()
+}
+
+/// A byte array nested inside another stream, which tdesktop parses with its own `QDataStream`.
+pub(crate) type Blob<'a> = Cursor<&'a [u8]>;
+
+pub(crate) fn at_end(blob: &Blob<'_>) -> bool {
+    blob.position() == blob.get_ref().len() as u64
+}
+
+/// Parses a blob stored in the setting or map entry read from `location`,
+/// pointing any errors at their offset inside it.
+pub(crate) fn parse_blob<T>(
+    serialized: &[u8],
+    location: Location,
+    parse: impl FnOnce(&mut Blob<'_>) -> std::io::Result<T>,
+) -> Result<T> {
+    let mut blob = Cursor::new(serialized);
+    let result = parse(&mut blob);
+    result.map_err(|e| {
+        // skip over the setting's kind and the blob's length prefix
+        let offset = location.offset + 8 + blob.position();
+        Error::from_io(e, Location { offset, ..location })
+    })
+}
+
 pub trait ValueStream {
     fn read_val<T: Readable>(&mut self) -> std::io::Result<T>;
     fn skip_val<T: Readable>(&mut self) -> std::io::Result<()>;
@@ -242,33 +595,162 @@ impl<R: Read> ValueStream for R {
     }
 }
 
+pub trait ValueSink {
+    fn write_val<T: Writable>(&mut self, val: &T) -> std::io::Result<()>;
+    fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()>;
+}
+
+impl<W: Write> ValueSink for W {
+    fn write_val<T: Writable>(&mut self, val: &T) -> std::io::Result<()> {
+        val.write_to(self)
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        // Qt writes an empty (null) byte array as a length of -1
+        if bytes.is_empty() {
+            return self.write_val(&u32::MAX);
+        }
+
+        self.write_val(&(bytes.len() as u32))?;
+        self.write_all(bytes)
+    }
+}
+
 pub trait StreamWithEnd {
     fn is_done(&self) -> bool;
     fn should_be_done(&self) -> Result<()>;
 }
 
-impl StreamWithEnd for FileReadDescriptor {
-    fn is_done(&self) -> bool {
-        self.data.position() == self.data.get_ref().len() as u64
+// The descriptors shadow `ValueStream` with inherent methods that attach a location to any errors,
+// while `Readable` impls still get the plain `std::io` versions on whatever stream they're handed.
+macro_rules! impl_descriptor_stream {
+    ($ty:ty) => {
+        impl $ty {
+            pub fn read_val<T: Readable>(&mut self) -> Result<T> {
+                let location = self.location();
+                T::read_from(&mut self.data).map_err(|e| Error::from_io(e, location))
+            }
+
+            pub fn skip_val<T: Readable>(&mut self) -> Result<()> {
+                let location = self.location();
+                T::skip_from(&mut self.data).map_err(|e| Error::from_io(e, location))
+            }
+
+            pub fn read_bytes(&mut self) -> Result<Vec<u8>> {
+                self.read_val::<Bytes>().map(|b| b.0)
+            }
+
+            pub fn skip_bytes(&mut self) -> Result<()> {
+                self.skip_val::<Bytes>()
+            }
+        }
+
+        impl StreamWithEnd for $ty {
+            fn is_done(&self) -> bool {
+                self.data.position() == self.data.get_ref().len() as u64
+            }
+
+            fn should_be_done(&self) -> Result<()> {
+                let pos = self.data.position();
+                let len = self.data.get_ref().len() as u64;
+                if pos != len {
+                    return Err(Error::ExtraneousData {
+                        location: self.location(),
+                        remaining: len - pos,
+                    });
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+impl_descriptor_stream!(FileReadDescriptor);
+impl_descriptor_stream!(EncryptedDescriptor);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_read_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        for stale in ["settings0", "settings1"] {
+            fs::write(dir.path().join(stale), b"stale").unwrap();
+        }
+
+        let mut file = FileWriteDescriptor::new("settings", dir.path(), 3_000_001);
+        file.write_val(&0x1234_5678u32).unwrap();
+        file.write_val(&String::from("tdata")).unwrap();
+        file.write_bytes(b"payload").unwrap();
+        file.finish().unwrap();
+
+        assert!(dir.path().join("settingss").exists());
+        for gone in ["settings0", "settings1", "settingss.tmp"] {
+            assert!(!dir.path().join(gone).exists(), "{} was left behind", gone);
+        }
+
+        let mut file = FileReadDescriptor::open("settings", dir.path()).unwrap();
+        assert_eq!(file.version(), 3_000_001);
+        assert_eq!(file.read_val::<u32>().unwrap(), 0x1234_5678);
+        assert_eq!(file.read_val::<String>().unwrap(), "tdata");
+        assert_eq!(file.read_bytes().unwrap(), b"payload");
+        file.should_be_done().unwrap();
     }
 
-    fn should_be_done(&self) -> Result<()> {
-        let pos = self.data.position();
-        let len = self.data.get_ref().len() as u64;
-        ensure!(pos == len, "extraneous data: {} bytes", len - pos);
-        Ok(())
+    #[test]
+    fn encrypt_decrypt_round_trip() {
+        let key = MtpAuthKey::create_local(b"", &[7; 32]);
+        for len in [0, 11, 12, 15, 16, 27, 28] {
+            let payload = (0..len as u8).collect::<Vec<u8>>();
+            let mut data = EncryptedDescriptor::new();
+            data.write_all(&payload).unwrap();
+            let encrypted = data.encrypt_local(&key);
+
+            // a 16-byte message key, then the length-prefixed payload padded to whole AES blocks
+            let full_size = (4 + len + 0x0F) & !0x0F;
+            assert_eq!(encrypted.len(), 16 + full_size, "payload of {}", len);
+            let (key128, ciphertext) = encrypted.split_at(16);
+            let decrypted = aes_decrypt_local(ciphertext, &key, key128.try_into().unwrap());
+            assert_eq!(decrypted[..4], (4 + len as u32).to_le_bytes());
+            assert_eq!(decrypted[4..4 + len], payload[..]);
+
+            let mut data =
+                EncryptedDescriptor::decrypt_local(&encrypted, &key, Location::default()).unwrap();
+            let mut read = Vec::new();
+            data.read_to_end(&mut read).unwrap();
+            assert_eq!(read, payload, "payload of {}", len);
+        }
     }
-}
 
-impl StreamWithEnd for EncryptedDescriptor {
-    fn is_done(&self) -> bool {
-        self.data.position() == self.data.get_ref().len() as u64
+    #[test]
+    fn open_reports_rejected_variants() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut file = FileWriteDescriptor::new("settings", dir.path(), 1);
+        file.write_val(&1u32).unwrap();
+        file.finish().unwrap();
+        fs::rename(dir.path().join("settingss"), dir.path().join("settings1")).unwrap();
+        fs::write(dir.path().join("settingss"), b"not a TDF$ file").unwrap();
+
+        let file = FileReadDescriptor::open("settings", dir.path()).unwrap();
+        assert_eq!(file.location().path, Path::new("settings1"));
+        assert!(matches!(file.rejected(), [Error::BadMagic { .. }]));
     }
 
-    fn should_be_done(&self) -> Result<()> {
-        let pos = self.data.position();
-        let len = self.data.get_ref().len() as u64;
-        ensure!(pos == len, "extraneous data: {} bytes", len - pos);
-        Ok(())
+    #[test]
+    fn write_read_encrypted_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let key = MtpAuthKey::create_local(b"", &[7; 32]);
+        let mut data = EncryptedDescriptor::new();
+        data.write_val(&String::from("secret")).unwrap();
+
+        let mut file = FileWriteDescriptor::new("key_data", dir.path(), 1);
+        file.write_encrypted(data, &key).unwrap();
+        file.finish().unwrap();
+
+        let mut file = FileReadDescriptor::open("key_data", dir.path()).unwrap();
+        let mut data = file.read_encrypted(&key).unwrap();
+        assert_eq!(data.read_val::<String>().unwrap(), "secret");
+        data.should_be_done().unwrap();
     }
 }
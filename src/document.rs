@@ -0,0 +1,174 @@
+use num_enum::TryFromPrimitive;
+use std::convert::TryInto;
+use std::io::Read;
+
+use crate::data::DocumentId;
+use crate::descriptor::{EncryptedDescriptor, ValueStream};
+use crate::error::{Error, Result};
+
+/// Written before the document's own version, by versions that have one.
+const VERSION_TAG: i32 = 0x7FFF_FFFF;
+
+/// `DocumentType`, from tdesktop's `data_document.h`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive)]
+#[repr(i32)]
+pub enum DocumentType {
+    File = 0,
+    Video = 1,
+    Song = 2,
+    Sticker = 3,
+    Animated = 4,
+    Voice = 5,
+    RoundVideo = 6,
+    WallPaper = 7,
+}
+
+#[derive(Debug, Clone)]
+pub struct StickerInfo {
+    /// The emoji the sticker stands for.
+    pub alt: String,
+    /// How the sticker's set was referenced: 0 for none, 1 by id and 2 by short name.
+    pub set_type: i32,
+}
+
+/// A document as `Serialize::Document` stores it, in sticker sets and saved GIFs.
+#[derive(Debug, Clone)]
+pub struct StoredDocument {
+    pub id: DocumentId,
+    pub access_hash: u64,
+    pub date: i32,
+    pub file_reference: Vec<u8>,
+    pub name: String,
+    pub mime: String,
+    pub dc_id: i32,
+    pub size: i64,
+    pub width: i32,
+    pub height: i32,
+    pub kind: DocumentType,
+    /// Only present for stickers.
+    pub sticker: Option<StickerInfo>,
+    /// In seconds, and only present for anything but stickers.
+    pub duration: Option<i32>,
+    /// tdesktop's serialized `ImageLocation`, which is empty for versions that predate it.
+    pub thumbnail: Vec<u8>,
+    pub thumbnail_byte_size: i32,
+    pub video_thumbnail: Vec<u8>,
+    pub video_thumbnail_byte_size: i32,
+    /// Whether `inline_thumbnail` is an SVG path rather than a tiny JPEG.
+    pub inline_thumbnail_is_path: bool,
+    pub inline_thumbnail: Vec<u8>,
+}
+
+/// Reads an image location, skipping over the fields of the pre-2.0 `StorageImageLocation`.
+pub(crate) fn read_image_location(
+    mut stream: impl Read,
+    app_version: i32,
+) -> std::io::Result<Vec<u8>> {
+    if app_version >= 2_000_000 {
+        return stream.read_bytes();
+    }
+
+    let (_width, _height, _dc) = stream.read_val::<(i32, i32, i32)>()?;
+    let (_volume, _local, _secret) = stream.read_val::<(u64, i32, u64)>()?;
+    if app_version >= 1_003_013 {
+        stream.skip_bytes()?;
+    }
+    Ok(Vec::new())
+}
+
+fn read_image_location_at(stream: &mut EncryptedDescriptor, app_version: i32) -> Result<Vec<u8>> {
+    let location = stream.location();
+    read_image_location(&mut *stream, app_version).map_err(|e| Error::from_io(e, location))
+}
+
+impl StoredDocument {
+    /// Reads a document out of a file written by `app_version`.
+    pub(crate) fn read(stream: &mut EncryptedDescriptor, app_version: i32) -> Result<Self> {
+        let id = stream.read_val()?;
+        let access_hash = stream.read_val()?;
+        let date = stream.read_val()?;
+
+        let mut file_reference = Vec::new();
+        let mut version = 0;
+        if app_version >= 9061 {
+            if app_version >= 1_003_013 {
+                file_reference = stream.read_bytes()?;
+            }
+            if stream.read_val::<i32>()? == VERSION_TAG {
+                version = stream.read_val::<i32>()?;
+            }
+        }
+
+        let name = stream.read_val()?;
+        let mime = stream.read_val()?;
+        let dc_id = stream.read_val()?;
+        let size = if version >= 5 {
+            stream.read_val()?
+        } else {
+            stream.read_val::<i32>()?.into()
+        };
+        let width = stream.read_val()?;
+        let height = stream.read_val()?;
+
+        let kind_location = stream.location();
+        let kind = stream
+            .read_val::<i32>()?
+            .try_into()
+            .map_err(|_| Error::invalid_data(kind_location, "unknown document type"))?;
+
+        let (sticker, duration) = if kind == DocumentType::Sticker {
+            let sticker = StickerInfo {
+                alt: stream.read_val()?,
+                set_type: stream.read_val()?,
+            };
+            (Some(sticker), None)
+        } else {
+            (None, Some(stream.read_val()?))
+        };
+
+        let mut result = Self {
+            id,
+            access_hash,
+            date,
+            file_reference,
+            name,
+            mime,
+            dc_id,
+            size,
+            width,
+            height,
+            kind,
+            sticker,
+            duration,
+            thumbnail: read_image_location_at(stream, app_version)?,
+            thumbnail_byte_size: 0,
+            video_thumbnail: Vec::new(),
+            video_thumbnail_byte_size: 0,
+            inline_thumbnail_is_path: false,
+            inline_thumbnail: Vec::new(),
+        };
+
+        if version >= 1 {
+            result.thumbnail_byte_size = stream.read_val()?;
+            result.video_thumbnail = read_image_location_at(stream, app_version)?;
+            result.video_thumbnail_byte_size = stream.read_val()?;
+            if version >= 2 {
+                result.inline_thumbnail_is_path = stream.read_val::<i32>()? == 1;
+                result.inline_thumbnail = stream.read_bytes()?;
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+/// Reads the documents of the saved GIFs.
+pub(crate) fn read_saved_gifs(
+    stream: &mut EncryptedDescriptor,
+    app_version: i32,
+) -> Result<Vec<StoredDocument>> {
+    let count = stream.read_val::<u32>()?;
+    (0..count)
+        .map(|_| StoredDocument::read(stream, app_version))
+        .collect()
+}
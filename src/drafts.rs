@@ -0,0 +1,389 @@
+use num_enum::TryFromPrimitive;
+use std::convert::TryFrom;
+
+use crate::data::{MsgId, PeerId};
+use crate::descriptor::{read_list, EncryptedDescriptor, StreamWithEnd, ValueStream};
+use crate::error::{Error, Result};
+
+const MULTI_DRAFT_TAG_OLD: u64 = 0xFFFF_FFFF_FFFF_FF01;
+const MULTI_DRAFT_CURSORS_TAG_OLD: u64 = 0xFFFF_FFFF_FFFF_FF02;
+const MULTI_DRAFT_TAG: u64 = 0xFFFF_FFFF_FFFF_FF03;
+const MULTI_DRAFT_CURSORS_TAG: u64 = 0xFFFF_FFFF_FFFF_FF04;
+
+/// Which of a chat's composers a draft belongs to, as tdesktop's `Data::DraftKey` encodes it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DraftKey(i64);
+
+impl DraftKey {
+    const LOCAL_INDEX: i64 = -1;
+    const SCHEDULED_INDEX: i64 = -3;
+    /// `ServerMaxMsgId`, which edit drafts are offset by.
+    const EDIT_SHIFT: i64 = 1 << 56;
+    /// The same, from before message ids were widened to 64 bits.
+    const EDIT_SHIFT_OLD: i64 = 0x3FFF_FFFF;
+
+    pub fn from_serialized(serialized: i64) -> Self {
+        Self(serialized)
+    }
+
+    /// Converts a key from the 32-bit form used by older multi-draft files.
+    pub fn from_serialized_old(serialized: i32) -> Self {
+        let serialized = i64::from(serialized);
+        if serialized >= Self::EDIT_SHIFT_OLD + Self::SCHEDULED_INDEX {
+            Self(serialized - Self::EDIT_SHIFT_OLD + Self::EDIT_SHIFT)
+        } else {
+            Self(serialized)
+        }
+    }
+
+    pub fn local(topic_root_id: MsgId) -> Self {
+        Self(if topic_root_id != 0 {
+            topic_root_id
+        } else {
+            Self::LOCAL_INDEX
+        })
+    }
+
+    pub fn local_edit(topic_root_id: MsgId) -> Self {
+        Self(Self::local(topic_root_id).0 + Self::EDIT_SHIFT)
+    }
+
+    pub fn serialize(self) -> i64 {
+        self.0
+    }
+
+    fn base(self) -> i64 {
+        if self.is_edit() {
+            self.0 - Self::EDIT_SHIFT
+        } else {
+            self.0
+        }
+    }
+
+    /// Whether this is the draft of an edit to an existing message, rather than of a new one.
+    pub fn is_edit(self) -> bool {
+        self.0 >= Self::EDIT_SHIFT + Self::SCHEDULED_INDEX
+    }
+
+    pub fn is_scheduled(self) -> bool {
+        self.base() == Self::SCHEDULED_INDEX
+    }
+
+    /// The forum topic the draft was written in, or zero outside of forums.
+    pub fn topic_root_id(self) -> MsgId {
+        self.base().max(0)
+    }
+}
+
+/// Whether the link preview was dismissed, mirroring `Data::PreviewState`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive)]
+#[repr(i32)]
+pub enum PreviewState {
+    Allowed = 0,
+    Cancelled = 1,
+    EmptyOnEdit = 2,
+}
+
+/// A formatting tag, as tdesktop's `TextWithTags` stores entities.
+#[derive(Debug, Clone)]
+pub struct TextTag {
+    pub offset: i32,
+    pub length: i32,
+    /// e.g. `**` for bold, or `mention://...` for a mention.
+    pub id: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Draft {
+    pub key: DraftKey,
+    pub text: String,
+    pub tags: Vec<TextTag>,
+    /// The message being replied to, or for edit drafts, the message being edited.
+    pub msg_id: MsgId,
+    pub preview_state: PreviewState,
+    /// Where the cursor and scroll bar were left, if that was saved.
+    pub cursor: Option<DraftCursor>,
+}
+
+/// The composer's state within the text of a [`Draft`], as `MessageCursor` stores it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DraftCursor {
+    pub position: i32,
+    /// The other end of the selection, equal to `position` when nothing is selected.
+    pub anchor: i32,
+    /// [`DraftCursor::SCROLL_TO_END`] if the composer was scrolled to the bottom.
+    pub scroll: i32,
+}
+
+impl DraftCursor {
+    /// `QFIXED_MAX`, which tdesktop uses to mean the end of the text.
+    pub const SCROLL_TO_END: i32 = i32::MAX / 256;
+}
+
+/// Every draft saved for one chat.
+#[derive(Debug, Clone)]
+pub struct PeerDrafts {
+    pub peer: PeerId,
+    pub drafts: Vec<Draft>,
+}
+
+fn deserialize_tags(serialized: &[u8]) -> std::io::Result<Vec<TextTag>> {
+    if serialized.is_empty() {
+        return Ok(Vec::new());
+    }
+    read_list(&mut &serialized[..], |s| {
+        Ok(TextTag {
+            offset: s.read_val()?,
+            length: s.read_val()?,
+            id: s.read_val()?,
+        })
+    })
+}
+
+fn read_tags(stream: &mut EncryptedDescriptor) -> Result<Vec<TextTag>> {
+    let location = stream.location();
+    let serialized = stream.read_bytes()?;
+    deserialize_tags(&serialized).map_err(|e| Error::from_io(e, location))
+}
+
+fn preview_state(unchecked: i32) -> PreviewState {
+    PreviewState::try_from(unchecked).unwrap_or(PreviewState::Allowed)
+}
+
+/// Reads the drafts saved for `peer`, or none if the file belongs to another peer.
+pub(crate) fn read_drafts(
+    stream: &mut EncryptedDescriptor,
+    version: i32,
+    peer: PeerId,
+) -> Result<Vec<Draft>> {
+    let tag = stream.read_val::<u64>()?;
+    if tag != MULTI_DRAFT_TAG && tag != MULTI_DRAFT_TAG_OLD {
+        return read_drafts_legacy(stream, version, peer, tag);
+    }
+
+    let draft_peer = stream.read_val::<PeerId>()?;
+    let count = stream.read_val::<u32>()?;
+    if draft_peer != peer {
+        return Ok(Vec::new());
+    }
+
+    let mut drafts = Vec::new();
+    for _ in 0..count {
+        let key = if tag == MULTI_DRAFT_TAG {
+            DraftKey::from_serialized(stream.read_val()?)
+        } else {
+            DraftKey::from_serialized_old(stream.read_val()?)
+        };
+        drafts.push(Draft {
+            key,
+            text: stream.read_val()?,
+            tags: read_tags(stream)?,
+            msg_id: stream.read_val()?,
+            preview_state: preview_state(stream.read_val()?),
+            cursor: None,
+        });
+    }
+    Ok(drafts)
+}
+
+/// Reads the format from before multiple drafts per chat, where the tag is really the peer id.
+fn read_drafts_legacy(
+    stream: &mut EncryptedDescriptor,
+    version: i32,
+    peer: PeerId,
+    draft_peer_serialized: u64,
+) -> Result<Vec<Draft>> {
+    if PeerId::from_serialized(draft_peer_serialized) != peer {
+        return Ok(Vec::new());
+    }
+
+    let mut local = Draft {
+        key: DraftKey::local(0),
+        text: stream.read_val()?,
+        tags: Vec::new(),
+        msg_id: 0,
+        preview_state: PreviewState::Allowed,
+        cursor: None,
+    };
+    if version >= 9048 {
+        local.tags = read_tags(stream)?;
+    }
+
+    let mut edit = None;
+    if version >= 7021 {
+        local.msg_id = stream.read_val::<i32>()?.into();
+        if version >= 8001 {
+            local.preview_state = preview_state(stream.read_val()?);
+            if !stream.is_done() {
+                let text = stream.read_val()?;
+                let tags = if version >= 9048 {
+                    read_tags(stream)?
+                } else {
+                    Vec::new()
+                };
+                edit = Some(Draft {
+                    key: DraftKey::local_edit(0),
+                    text,
+                    tags,
+                    msg_id: stream.read_val::<i32>()?.into(),
+                    preview_state: preview_state(stream.read_val()?),
+                    cursor: None,
+                });
+            }
+        }
+    }
+
+    let mut drafts = Vec::new();
+    if local.msg_id != 0 || !local.text.is_empty() {
+        drafts.push(local);
+    }
+    drafts.extend(edit.filter(|e| e.msg_id != 0));
+    Ok(drafts)
+}
+
+fn read_cursor(stream: &mut EncryptedDescriptor) -> Result<DraftCursor> {
+    Ok(DraftCursor {
+        position: stream.read_val()?,
+        anchor: stream.read_val()?,
+        scroll: stream.read_val()?,
+    })
+}
+
+/// Reads a decrypted draft cursors file, and attaches the cursors to the matching `drafts`.
+pub(crate) fn read_draft_cursors(
+    stream: &mut EncryptedDescriptor,
+    peer: PeerId,
+    drafts: &mut [Draft],
+) -> Result<()> {
+    let mut attach = |key, cursor| {
+        if let Some(draft) = drafts.iter_mut().find(|d| d.key == key) {
+            draft.cursor = Some(cursor);
+        }
+    };
+
+    let tag = stream.read_val::<u64>()?;
+    if tag != MULTI_DRAFT_CURSORS_TAG
+        && tag != MULTI_DRAFT_CURSORS_TAG_OLD
+        && tag != MULTI_DRAFT_TAG_OLD
+    {
+        // the legacy format, with a cursor for the local draft and maybe one for the edit draft
+        if PeerId::from_serialized(tag) != peer {
+            return Ok(());
+        }
+        attach(DraftKey::local(0), read_cursor(stream)?);
+        if !stream.is_done() {
+            attach(DraftKey::local_edit(0), read_cursor(stream)?);
+        }
+        return Ok(());
+    }
+
+    let draft_peer = stream.read_val::<PeerId>()?;
+    let count = stream.read_val::<u32>()?;
+    if draft_peer != peer {
+        return Ok(());
+    }
+
+    for _ in 0..count {
+        let key = if tag == MULTI_DRAFT_CURSORS_TAG {
+            DraftKey::from_serialized(stream.read_val()?)
+        } else {
+            DraftKey::from_serialized_old(stream.read_val()?)
+        };
+        attach(key, read_cursor(stream)?);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::UserId;
+    use crate::descriptor::ValueSink;
+
+    const VERSION: i32 = 4_008_003;
+
+    fn peer() -> PeerId {
+        PeerId::from_chat_id(UserId::new(777))
+    }
+
+    fn write_bold_tags(data: &mut EncryptedDescriptor) {
+        let mut tags = Vec::new();
+        tags.write_val(&(1i32, 0i32, 5i32, String::from("**")))
+            .unwrap();
+        data.write_bytes(&tags).unwrap();
+    }
+
+    #[test]
+    fn read_multi_drafts() {
+        let mut data = EncryptedDescriptor::new();
+        data.write_val(&(MULTI_DRAFT_TAG, peer().serialize(), 2u32))
+            .unwrap();
+        data.write_val(&(DraftKey::local(0).serialize(), String::from("hello world")))
+            .unwrap();
+        write_bold_tags(&mut data);
+        data.write_val(&(10i64, 1i32)).unwrap();
+        data.write_val(&(DraftKey::local_edit(55).serialize(), String::from("edited")))
+            .unwrap();
+        data.write_bytes(b"").unwrap();
+        data.write_val(&(60i64, 0i32)).unwrap();
+
+        let mut stream = data.reopen();
+        let drafts = read_drafts(&mut stream, VERSION, peer()).unwrap();
+        stream.should_be_done().unwrap();
+        assert_eq!(drafts.len(), 2);
+
+        let local = &drafts[0];
+        assert!(!local.key.is_edit());
+        assert_eq!(local.text, "hello world");
+        assert_eq!(local.tags.len(), 1);
+        assert_eq!((local.tags[0].offset, local.tags[0].length), (0, 5));
+        assert_eq!(local.tags[0].id, "**");
+        assert_eq!(local.msg_id, 10);
+        assert_eq!(local.preview_state, PreviewState::Cancelled);
+
+        let edit = &drafts[1];
+        assert!(edit.key.is_edit());
+        assert_eq!(edit.key.topic_root_id(), 55);
+        assert_eq!(edit.text, "edited");
+        assert!(edit.tags.is_empty());
+        assert_eq!(edit.msg_id, 60);
+    }
+
+    #[test]
+    fn read_drafts_of_other_peer() {
+        let other = PeerId::from_chat_id(UserId::new(778));
+        let mut data = EncryptedDescriptor::new();
+        data.write_val(&(MULTI_DRAFT_TAG, other.serialize(), 1u32))
+            .unwrap();
+        data.write_val(&(DraftKey::local(0).serialize(), String::from("hello")))
+            .unwrap();
+
+        let mut stream = data.reopen();
+        assert!(read_drafts(&mut stream, VERSION, peer())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn read_legacy_drafts() {
+        let mut data = EncryptedDescriptor::new();
+        data.write_val(&(peer().serialize(), String::from("hello")))
+            .unwrap();
+        write_bold_tags(&mut data);
+        data.write_val(&(0i32, 0i32, String::from("edited")))
+            .unwrap();
+        data.write_bytes(b"").unwrap();
+        data.write_val(&(60i32, 2i32)).unwrap();
+
+        let mut stream = data.reopen();
+        let drafts = read_drafts(&mut stream, 9048, peer()).unwrap();
+        stream.should_be_done().unwrap();
+        assert_eq!(drafts.len(), 2);
+        assert_eq!(drafts[0].key, DraftKey::local(0));
+        assert_eq!(drafts[0].text, "hello");
+        assert_eq!(drafts[0].tags[0].id, "**");
+        assert_eq!(drafts[1].key, DraftKey::local_edit(0));
+        assert_eq!(drafts[1].msg_id, 60);
+        assert_eq!(drafts[1].preview_state, PreviewState::EmptyOnEdit);
+    }
+}
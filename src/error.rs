@@ -0,0 +1,155 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::schema::SettingKind;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Where in the tdata directory an error happened.
+#[derive(Debug, Clone, Default)]
+pub struct Location {
+    /// The name the file was opened by, e.g. `map`.
+    pub file: String,
+    /// The path of the file that was actually read, relative to the tdata directory.
+    pub path: PathBuf,
+    /// The offset into the file, or into the decrypted data if the file's contents were encrypted.
+    pub offset: u64,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at offset {}", self.path.display(), self.offset)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum Error {
+    #[error("{location}: file not found")]
+    NotFound { location: Location },
+    #[error("{location}: no valid file: {}", join(.rejected))]
+    NoValidFile {
+        location: Location,
+        rejected: Vec<Error>,
+    },
+    #[error("{location}: bad magic")]
+    BadMagic { location: Location },
+    #[error("{location}: signature mismatch")]
+    SignatureMismatch { location: Location },
+    #[error("{location}: bad encrypted part size")]
+    BadEncryptedSize { location: Location },
+    #[error("{location}: bad decrypt key")]
+    BadDecryptKey { location: Location },
+    /// The local passcode didn't decrypt the key file.
+    #[error("{location}: wrong passcode")]
+    WrongPasscode { location: Location },
+    #[error("{location}: bad decrypted part")]
+    BadDecryptedPart { location: Location },
+    #[error("{location}: extraneous data: {remaining} bytes")]
+    ExtraneousData { location: Location, remaining: u64 },
+    #[error("{location}: unexpected end of data")]
+    UnexpectedEof { location: Location },
+    #[error("{location}: {reason}")]
+    InvalidData { location: Location, reason: String },
+    #[error("{location}: unsupported setting: {kind:?}")]
+    UnsupportedSetting {
+        location: Location,
+        kind: SettingKind,
+    },
+    #[error("{location}: theme file too large (should be less than 5 MB, got {size})")]
+    ThemeTooLarge { location: Location, size: u64 },
+    #[error("{location}: invalid theme cache: {reason}")]
+    InvalidCache {
+        location: Location,
+        reason: &'static str,
+    },
+    #[error("{location}: {source}")]
+    Io {
+        location: Location,
+        #[source]
+        source: io::Error,
+    },
+}
+
+fn join(errors: &[Error]) -> String {
+    let messages: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    messages.join("; ")
+}
+
+impl Error {
+    pub fn location(&self) -> &Location {
+        match self {
+            Self::NotFound { location }
+            | Self::NoValidFile { location, .. }
+            | Self::BadMagic { location }
+            | Self::SignatureMismatch { location }
+            | Self::BadEncryptedSize { location }
+            | Self::BadDecryptKey { location }
+            | Self::WrongPasscode { location }
+            | Self::BadDecryptedPart { location }
+            | Self::ExtraneousData { location, .. }
+            | Self::UnexpectedEof { location }
+            | Self::InvalidData { location, .. }
+            | Self::UnsupportedSetting { location, .. }
+            | Self::ThemeTooLarge { location, .. }
+            | Self::InvalidCache { location, .. }
+            | Self::Io { location, .. } => location,
+        }
+    }
+
+    fn location_mut(&mut self) -> &mut Location {
+        match self {
+            Self::NotFound { location }
+            | Self::NoValidFile { location, .. }
+            | Self::BadMagic { location }
+            | Self::SignatureMismatch { location }
+            | Self::BadEncryptedSize { location }
+            | Self::BadDecryptKey { location }
+            | Self::WrongPasscode { location }
+            | Self::BadDecryptedPart { location }
+            | Self::ExtraneousData { location, .. }
+            | Self::UnexpectedEof { location }
+            | Self::InvalidData { location, .. }
+            | Self::UnsupportedSetting { location, .. }
+            | Self::ThemeTooLarge { location, .. }
+            | Self::InvalidCache { location, .. }
+            | Self::Io { location, .. } => location,
+        }
+    }
+
+    pub(crate) fn invalid_data(location: Location, reason: impl Into<String>) -> Self {
+        Self::InvalidData {
+            location,
+            reason: reason.into(),
+        }
+    }
+
+    /// Attaches a location to an error raised while reading a stream.
+    ///
+    /// Errors raised by [`Readable`](crate::descriptor::Readable) impls don't know where they are,
+    /// so they smuggle an `Error` through `io::Error` and get their location filled in here.
+    pub(crate) fn from_io(err: io::Error, location: Location) -> Self {
+        if matches!(err.get_ref(), Some(inner) if inner.is::<Self>()) {
+            let mut inner = *err.into_inner().unwrap().downcast::<Self>().unwrap();
+            *inner.location_mut() = location;
+            return inner;
+        }
+
+        match err.kind() {
+            io::ErrorKind::NotFound => Self::NotFound { location },
+            io::ErrorKind::UnexpectedEof => Self::UnexpectedEof { location },
+            io::ErrorKind::InvalidData => Self::invalid_data(location, err.to_string()),
+            _ => Self::Io {
+                location,
+                source: err,
+            },
+        }
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
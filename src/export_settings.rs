@@ -0,0 +1,153 @@
+use crate::data::{ChannelId, ChatId, UserId};
+use crate::descriptor::{EncryptedDescriptor, StreamWithEnd};
+use crate::error::Result;
+
+const SINGLE_PEER_TYPE_EMPTY: i32 = 0;
+const SINGLE_PEER_TYPE_USER_OLD: i32 = 1;
+const SINGLE_PEER_TYPE_CHAT_OLD: i32 = 2;
+const SINGLE_PEER_TYPE_CHANNEL_OLD: i32 = 3;
+const SINGLE_PEER_TYPE_SELF: i32 = 4;
+const SINGLE_PEER_TYPE_USER: i32 = 8 + 1;
+const SINGLE_PEER_TYPE_CHAT: i32 = 8 + 2;
+const SINGLE_PEER_TYPE_CHANNEL: i32 = 8 + 3;
+
+/// The one chat an export was limited to, when it was started from that chat.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExportPeer {
+    SelfUser,
+    User { id: UserId, access_hash: u64 },
+    Chat { id: ChatId },
+    Channel { id: ChannelId, access_hash: u64 },
+}
+
+/// The options last used for exporting chat history, as `Export::Settings` stores them.
+#[derive(Debug, Clone, Default)]
+pub struct ExportSettings {
+    /// `Export::Settings::Types`, for which kinds of data to export.
+    pub types: u32,
+    /// The subset of `types` for which every chat is exported, rather than only the ones left.
+    pub full_chats: u32,
+    /// `Export::MediaSettings::Types`, for which kinds of media to download.
+    pub media_types: u32,
+    /// In bytes.
+    pub media_size_limit: u32,
+    /// `Export::Output::Format`: 0 for HTML, 1 for JSON and 2 for both.
+    pub format: u32,
+    pub path: String,
+    /// When the export may next be run, for exports the server asked to delay.
+    pub available_at: u32,
+    pub single_peer: Option<ExportPeer>,
+    /// The start of the date range, or zero for the beginning of the chat.
+    pub single_peer_from: i32,
+    /// The end of the date range, or zero for the end of the chat.
+    pub single_peer_till: i32,
+}
+
+/// Reads a decrypted export settings file.
+///
+/// Stops at a single peer of an unknown type, leaving it and its date range unset.
+pub(crate) fn read_export_settings(stream: &mut EncryptedDescriptor) -> Result<ExportSettings> {
+    let mut result = ExportSettings {
+        types: stream.read_val()?,
+        full_chats: stream.read_val()?,
+        media_types: stream.read_val()?,
+        media_size_limit: stream.read_val()?,
+        format: stream.read_val()?,
+        path: stream.read_val()?,
+        available_at: stream.read_val()?,
+        ..Default::default()
+    };
+
+    if !stream.is_done() {
+        let single_peer_type = stream.read_val::<i32>()?;
+        let bare_id = match single_peer_type {
+            SINGLE_PEER_TYPE_USER_OLD
+            | SINGLE_PEER_TYPE_CHAT_OLD
+            | SINGLE_PEER_TYPE_CHANNEL_OLD => stream.read_val::<i32>()? as u32 as u64,
+            SINGLE_PEER_TYPE_USER | SINGLE_PEER_TYPE_CHAT | SINGLE_PEER_TYPE_CHANNEL => {
+                stream.read_val()?
+            }
+            SINGLE_PEER_TYPE_SELF | SINGLE_PEER_TYPE_EMPTY => 0,
+            // with no way to tell how long the peer is, nothing after it can be read
+            _ => return Ok(result),
+        };
+
+        result.single_peer = match single_peer_type {
+            SINGLE_PEER_TYPE_USER_OLD | SINGLE_PEER_TYPE_USER => Some(ExportPeer::User {
+                id: UserId::new(bare_id),
+                access_hash: stream.read_val()?,
+            }),
+            SINGLE_PEER_TYPE_CHAT_OLD | SINGLE_PEER_TYPE_CHAT => Some(ExportPeer::Chat {
+                id: ChatId::new(bare_id),
+            }),
+            SINGLE_PEER_TYPE_CHANNEL_OLD | SINGLE_PEER_TYPE_CHANNEL => Some(ExportPeer::Channel {
+                id: ChannelId::new(bare_id),
+                access_hash: stream.read_val()?,
+            }),
+            SINGLE_PEER_TYPE_SELF => Some(ExportPeer::SelfUser),
+            _ => None,
+        };
+    }
+
+    if !stream.is_done() {
+        result.single_peer_from = stream.read_val()?;
+        result.single_peer_till = stream.read_val()?;
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::ValueSink;
+
+    fn write_common(data: &mut EncryptedDescriptor) {
+        data.write_val(&(0x3u32, 0x1u32, 0x7u32, 8_388_608u32))
+            .unwrap();
+        data.write_val(&1u32).unwrap();
+        data.write_val(&String::from("/home/user/Export")).unwrap();
+        data.write_val(&0u32).unwrap();
+    }
+
+    #[test]
+    fn read_single_channel() {
+        let mut data = EncryptedDescriptor::new();
+        write_common(&mut data);
+        data.write_val(&(SINGLE_PEER_TYPE_CHANNEL, 1_234_567_890u64, 42u64))
+            .unwrap();
+        data.write_val(&(1_600_000_000i32, 1_700_000_000i32))
+            .unwrap();
+
+        let mut stream = data.reopen();
+        let result = read_export_settings(&mut stream).unwrap();
+        stream.should_be_done().unwrap();
+        assert_eq!(result.path, "/home/user/Export");
+        assert_eq!(result.format, 1);
+        assert_eq!(
+            result.single_peer,
+            Some(ExportPeer::Channel {
+                id: ChannelId::new(1_234_567_890),
+                access_hash: 42,
+            })
+        );
+        assert_eq!(result.single_peer_from, 1_600_000_000);
+        assert_eq!(result.single_peer_till, 1_700_000_000);
+    }
+
+    #[test]
+    fn read_unknown_single_peer_type() {
+        let mut data = EncryptedDescriptor::new();
+        write_common(&mut data);
+        data.write_val(&(8 + 7i32, 1u64, 2u64, 3u64)).unwrap();
+        data.write_val(&(1_600_000_000i32, 1_700_000_000i32))
+            .unwrap();
+
+        let mut stream = data.reopen();
+        let result = read_export_settings(&mut stream).unwrap();
+        assert_eq!(result.path, "/home/user/Export");
+        assert_eq!(result.single_peer, None);
+        assert_eq!(result.single_peer_from, 0);
+        assert_eq!(result.single_peer_till, 0);
+    }
+}
@@ -0,0 +1,89 @@
+use serde::Serialize;
+
+use crate::descriptor::EncryptedDescriptor;
+use crate::error::Result;
+
+/// Which of the legacy media maps a record came from, which decides how its file is laid out.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum LegacyMediaKind {
+    Image,
+    StickerImage,
+    Audio,
+}
+
+/// The format of some media, as told by its first few bytes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum MediaType {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Ogg,
+    Mp3,
+    Mp4,
+    Unknown,
+}
+
+impl MediaType {
+    pub fn detect(data: &[u8]) -> Self {
+        match data {
+            [0xFF, 0xD8, 0xFF, ..] => Self::Jpeg,
+            [0x89, b'P', b'N', b'G', ..] => Self::Png,
+            [b'G', b'I', b'F', b'8', ..] => Self::Gif,
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Self::Webp,
+            [b'O', b'g', b'g', b'S', ..] => Self::Ogg,
+            [b'I', b'D', b'3', ..] | [0xFF, 0xE0..=0xFF, ..] => Self::Mp3,
+            [_, _, _, _, b'f', b't', b'y', b'p', ..] => Self::Mp4,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// The usual file extension for this type, without the dot.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Jpeg => "jpg",
+            Self::Png => "png",
+            Self::Gif => "gif",
+            Self::Webp => "webp",
+            Self::Ogg => "ogg",
+            Self::Mp3 => "mp3",
+            Self::Mp4 => "mp4",
+            Self::Unknown => "bin",
+        }
+    }
+}
+
+/// Media recovered from one of the legacy caches.
+#[derive(Debug, Clone)]
+pub struct LegacyMedia {
+    pub kind: LegacyMediaKind,
+    /// The `StorageKey` the media was downloaded from.
+    pub location: (u64, u64),
+    pub media_type: MediaType,
+    pub data: Vec<u8>,
+}
+
+/// Reads the media cached for `location`, or nothing if the file holds some other download.
+pub(crate) fn read_legacy_media(
+    stream: &mut EncryptedDescriptor,
+    kind: LegacyMediaKind,
+    location: (u64, u64),
+) -> Result<Option<LegacyMedia>> {
+    let file_location = stream.read_val::<(u64, u64)>()?;
+    if kind == LegacyMediaKind::Image {
+        // the `storage.FileType` the image was downloaded as, which tdesktop stopped trusting
+        let _legacy_type = stream.read_val::<u32>()?;
+    }
+    let data = stream.read_bytes()?;
+
+    if file_location != location {
+        return Ok(None);
+    }
+
+    Ok(Some(LegacyMedia {
+        kind,
+        location,
+        media_type: MediaType::detect(&data),
+        data,
+    }))
+}
@@ -0,0 +1,346 @@
+use std::collections::HashSet;
+use std::convert::TryInto;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde::Serialize;
+
+pub mod descriptor;
+use descriptor::{FileReadDescriptor, StreamWithEnd};
+
+pub mod error;
+pub use error::{Error, Result};
+
+mod crypto;
+pub use crypto::MtpAuthKey;
+use crypto::{aes_decrypt_local, aes_encrypt_local};
+
+pub mod settings;
+
+pub mod schema;
+use schema::Setting;
+
+pub mod color;
+
+pub mod palette;
+use palette::Palette;
+
+pub mod data;
+
+pub mod session_settings;
+
+pub mod core_settings;
+use core_settings::CoreSettings;
+
+pub mod authorization;
+
+pub mod session_export;
+
+pub mod drafts;
+
+pub mod document;
+
+pub mod stickers;
+
+pub mod peer;
+
+pub mod recent;
+
+pub mod legacy_media;
+
+pub mod locations;
+
+pub mod export_settings;
+
+pub mod background;
+use background::{Background, BackgroundImage, BackgroundSource, ThemeBackground};
+
+pub mod account;
+pub use account::{Account, StorageMap};
+
+pub mod theme;
+pub use theme::SavedTheme;
+
+const MAX_ACCOUNTS: i32 = 3;
+
+// this is some wacky linking stuff in tdesktop
+const fn c_data_file() -> &'static str {
+    "data"
+}
+
+fn compute_key_name(data_name: &str) -> String {
+    format!("key_{}", data_name)
+}
+
+fn compose_data_string(data_name: &str, index: i32) -> String {
+    let mut result = data_name.replace('#', "");
+    if index > 0 {
+        write!(&mut result, "#{}", index + 1).unwrap();
+    }
+    result
+}
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize)]
+pub struct FileKey(u64);
+
+impl FileKey {
+    fn compute(data_name: &str) -> Self {
+        let hash = md5::compute(data_name);
+        let first_half = hash[..8].try_into().unwrap();
+        Self(u64::from_le_bytes(first_half))
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn to_file_part(self) -> String {
+        let mut val = self.0;
+        let mut result = String::with_capacity(16);
+        for _ in 0..16 {
+            let v = (val & 0x0F) as u8;
+            let c = if v < 0x0A {
+                b'0' + v
+            } else {
+                b'A' + (v - 0x0A)
+            };
+            result.push(c as char);
+            val >>= 4;
+        }
+        result
+    }
+}
+
+/// A tdata directory, along with everything read out of it so far.
+pub struct TdataReader {
+    base_path: PathBuf,
+    settings_key: Rc<MtpAuthKey>,
+    settings: Vec<Setting>,
+    core_settings: Option<CoreSettings>,
+    accounts: Vec<Account>,
+}
+
+impl TdataReader {
+    /// Opens the tdata directory at `base_path` and reads its global settings,
+    /// which are not protected by the local passcode.
+    pub fn open(base_path: impl Into<PathBuf>) -> Result<Self> {
+        let base_path = base_path.into();
+
+        let mut settings_data = FileReadDescriptor::open("settings", &base_path)?;
+        let salt_location = settings_data.location();
+        let salt = settings_data.read_bytes()?;
+        let salt = salt[..]
+            .try_into()
+            .map_err(|_| Error::invalid_data(salt_location, "bad salt size"))?;
+        let settings_key = MtpAuthKey::create_legacy_local(b"", salt);
+        let mut stream = settings_data.read_encrypted(&settings_key)?;
+        settings_data.should_be_done()?;
+
+        let mut settings = Vec::new();
+        let mut core_settings = None;
+        while !stream.is_done() {
+            let location = stream.location();
+            let setting = stream.read_val::<Setting>()?;
+            if let Setting::ApplicationSettings { serialized } = &setting {
+                core_settings = Some(CoreSettings::from_serialized(
+                    serialized,
+                    settings_data.version(),
+                    location,
+                )?);
+            }
+            settings.push(setting);
+        }
+
+        Ok(Self {
+            base_path,
+            settings_key,
+            settings,
+            core_settings,
+            accounts: Vec::new(),
+        })
+    }
+
+    pub fn base_path(&self) -> &Path {
+        &self.base_path
+    }
+
+    pub fn settings(&self) -> &[Setting] {
+        &self.settings
+    }
+
+    /// The application-wide settings, if the settings file had any.
+    pub fn core_settings(&self) -> Option<&CoreSettings> {
+        self.core_settings.as_ref()
+    }
+
+    /// The accounts read by the last successful call to [`unlock`](Self::unlock).
+    pub fn accounts(&self) -> &[Account] {
+        &self.accounts
+    }
+
+    /// Decrypts the local key with `passcode` and reads every account it protects.
+    ///
+    /// Installs without a local passcode are unlocked with an empty one.
+    /// A passcode that fails to decrypt the key is reported as [`Error::WrongPasscode`].
+    pub fn unlock(&mut self, passcode: &[u8]) -> Result<()> {
+        let data_name = c_data_file(); // a field
+        let name = compute_key_name(data_name);
+
+        let mut key_data = FileReadDescriptor::open(name, &self.base_path)?;
+
+        let salt_location = key_data.location();
+        let salt = key_data.read_bytes()?;
+        let salt = salt
+            .as_slice()
+            .try_into()
+            .map_err(|_| Error::invalid_data(salt_location, "bad salt in info file"))?;
+
+        let passcode_key = MtpAuthKey::create_local(passcode, salt);
+
+        let mut key_inner_data = key_data
+            .read_encrypted(&passcode_key)
+            .map_err(|e| match e {
+                Error::BadDecryptKey { location } => Error::WrongPasscode { location },
+                e => e,
+            })?;
+        let local_key = key_inner_data.read_val::<Rc<MtpAuthKey>>()?;
+        key_inner_data.should_be_done()?;
+
+        let mut info = key_data.read_encrypted(&local_key)?;
+        key_data.should_be_done()?;
+
+        let count_location = info.location();
+        let count = info.read_val::<i32>()?;
+        if count <= 0 || count > MAX_ACCOUNTS {
+            return Err(Error::invalid_data(count_location, "bad accounts count"));
+        }
+
+        let mut tried = HashSet::new();
+        let mut accounts = Vec::new();
+        for _ in 0..count {
+            let index = info.read_val::<i32>()?;
+            if !((0..MAX_ACCOUNTS).contains(&index) && tried.insert(index)) {
+                continue;
+            }
+
+            let mut account = Account::new(data_name, index, &self.base_path);
+            account.prepare_to_start(Rc::clone(&local_key))?;
+            account.session_settings = account.local.read_session_settings()?;
+            account.authorization = account.local.read_mtp_data()?;
+            accounts.push(account);
+        }
+
+        self.accounts = accounts;
+        Ok(())
+    }
+
+    /// Whether the `ThemeKey` setting has night mode switched on.
+    pub fn night_mode(&self) -> bool {
+        self.settings
+            .iter()
+            .any(|s| matches!(s, Setting::ThemeKey { night_mode, .. } if *night_mode))
+    }
+
+    /// Reads the theme selected by the `ThemeKey` setting, if there is one.
+    pub fn theme(&self) -> Result<Option<SavedTheme>> {
+        for setting in &self.settings {
+            if let Setting::ThemeKey {
+                day,
+                night,
+                night_mode,
+            } = *setting
+            {
+                let key = if night_mode { night } else { day };
+                let read = theme::read_theme_using_key(key, &self.settings_key, &self.base_path)?;
+                if read.object.content.is_empty() {
+                    return Err(Error::invalid_data(read.location.clone(), "empty content"));
+                }
+                return Ok(Some(read));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Reads the background named by the `BackgroundKey` setting for day or `night` mode, if there is one.
+    pub fn background(&self, night: bool) -> Result<Option<Background>> {
+        for setting in &self.settings {
+            if let Setting::BackgroundKey {
+                day,
+                night: night_key,
+            } = *setting
+            {
+                let key = if night { night_key } else { day };
+                if key.is_empty() {
+                    return Ok(None);
+                }
+                let mut file = match FileReadDescriptor::open(key.to_file_part(), &self.base_path) {
+                    Ok(file) => file,
+                    Err(Error::NotFound { .. }) => return Ok(None),
+                    Err(e) => return Err(e),
+                };
+                let mut stream = file.read_encrypted(&self.settings_key)?;
+                return background::read_background(&mut stream).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Works out the background the user sees in the active day or night mode.
+    ///
+    /// A background file takes precedence, unless it just stands for the theme's own background.
+    pub fn theme_background(&self) -> Result<Option<ThemeBackground>> {
+        let night = self.night_mode();
+        if let Some(file) = self.background(night)? {
+            if !file.paper.is_theme() {
+                return Ok(Some(self.file_background(night, file)));
+            }
+        }
+
+        if let Some(theme) = self.theme()? {
+            if let Some(image) = theme.background_from_cache()? {
+                return Ok(Some(ThemeBackground::new(
+                    BackgroundSource::Theme,
+                    theme.cache.tiled,
+                    None,
+                    BackgroundImage::Encoded(image.to_vec()),
+                )));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn file_background(&self, night: bool, file: Background) -> ThemeBackground {
+        let tiled = self.settings.iter().any(|s| match *s {
+            Setting::TileBackground {
+                day,
+                night: night_tile,
+            } => (if night { night_tile } else { day }) == 1,
+            _ => false,
+        });
+        ThemeBackground::new(BackgroundSource::File, tiled, Some(file.paper), file.image)
+    }
+
+    /// Writes the active theme to `path` as a `.tdesktop-theme` archive, with the background the user sees.
+    ///
+    /// Returns `false` if no theme is selected.
+    pub fn export_theme(&self, path: impl AsRef<Path>) -> Result<bool> {
+        let theme = match self.theme()? {
+            Some(theme) => theme,
+            None => return Ok(false),
+        };
+        let background = self.theme_background()?;
+        theme.export_archive(background.as_ref(), path)?;
+        Ok(true)
+    }
+
+    /// The palette tdesktop would apply for `saved`, unless a theme is being edited.
+    pub fn palette(&self, saved: &SavedTheme) -> Option<Box<Palette>> {
+        if theme::read_editing_palette(&self.base_path).is_none() {
+            saved.palette_from_cache().ok()
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::io::Read;
+
+use crate::data::DocumentId;
+use crate::descriptor::{EncryptedDescriptor, Readable, StreamWithEnd, ValueStream};
+use crate::error::Result;
+use crate::FileKey;
+
+/// Identifies a downloaded file, as tdesktop's `MediaKey` does.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MediaKey {
+    /// The location type in the top 32 bits, and the DC id in the bottom 32.
+    pub first: u64,
+    /// The id of the document, or whatever else the file belongs to.
+    pub second: u64,
+}
+
+impl MediaKey {
+    pub const DOCUMENT: u32 = 0x4e45abe9;
+    pub const AUDIO: u32 = 0x74dc404d;
+    pub const VIDEO: u32 = 0x3d0364ec;
+    pub const SECURE: u32 = 0xcbc7ee28;
+
+    pub fn location_type(self) -> u32 {
+        (self.first >> 32) as u32
+    }
+
+    pub fn dc_id(self) -> i32 {
+        self.first as i32
+    }
+
+    pub fn id(self) -> u64 {
+        self.second
+    }
+}
+
+/// A `QDateTime`, as a `QDataStream` at tdesktop's `Qt_5_1` version writes it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DateTime {
+    /// `i64::MIN` if the date is null.
+    pub julian_day: i64,
+    /// `u32::MAX` if the time is null.
+    pub msecs_since_midnight: u32,
+    /// `QDateTimePrivate::Spec`, where 2 is UTC and anything below it is local time.
+    ///
+    /// This version of the format doesn't store the offset or time zone that goes with the others.
+    pub spec: i8,
+}
+
+impl DateTime {
+    const UNIX_EPOCH_JULIAN_DAY: i64 = 2_440_588;
+
+    /// Seconds since the Unix epoch, treating local times as UTC.
+    pub fn unix_time(self) -> Option<i64> {
+        if self.julian_day == i64::MIN || self.msecs_since_midnight == u32::MAX {
+            return None;
+        }
+        let days = self.julian_day - Self::UNIX_EPOCH_JULIAN_DAY;
+        Some(days * 86_400 + i64::from(self.msecs_since_midnight / 1000))
+    }
+}
+
+impl Readable for DateTime {
+    fn read_from(mut stream: impl Read) -> std::io::Result<Self> {
+        Ok(Self {
+            julian_day: stream.read_val()?,
+            msecs_since_midnight: stream.read_val()?,
+            spec: stream.read_val::<u8>()? as i8,
+        })
+    }
+}
+
+/// Where a downloaded file was saved, as `Core::FileLocation` stores it.
+#[derive(Debug, Clone)]
+pub struct FileLocation {
+    pub key: MediaKey,
+    pub path: String,
+    /// A macOS security-scoped bookmark for `path`, which is empty elsewhere.
+    pub bookmark: Vec<u8>,
+    pub modified: DateTime,
+    pub size: i32,
+}
+
+/// A file downloaded over HTTP, which is kept in the media cache under `key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WebFileLocation {
+    pub url: String,
+    pub key: FileKey,
+    pub size: u32,
+}
+
+/// The index of downloaded files, read from an account's locations file.
+#[derive(Debug, Clone, Default)]
+pub struct FileLocations {
+    pub locations: Vec<FileLocation>,
+    /// Keys whose file is the same as that of another key.
+    pub aliases: Vec<(MediaKey, MediaKey)>,
+    pub web_files: Vec<WebFileLocation>,
+}
+
+impl FileLocations {
+    /// Finds where the file for `key` was saved, following aliases.
+    pub fn find(&self, key: MediaKey) -> Option<&FileLocation> {
+        let direct = self.locations.iter().find(|l| l.key == key);
+        direct.or_else(|| {
+            let (_, target) = self.aliases.iter().find(|(alias, _)| *alias == key)?;
+            self.locations.iter().find(|l| l.key == *target)
+        })
+    }
+
+    /// Finds where the document with the given id was saved, whatever DC it came from.
+    pub fn find_document(&self, id: DocumentId) -> Option<&FileLocation> {
+        let is_document = |key: MediaKey| {
+            key.id() == id
+                && matches!(
+                    key.location_type(),
+                    MediaKey::DOCUMENT | MediaKey::AUDIO | MediaKey::VIDEO
+                )
+        };
+        let key = self
+            .locations
+            .iter()
+            .map(|l| l.key)
+            .chain(self.aliases.iter().map(|&(alias, _)| alias))
+            .find(|&key| is_document(key))?;
+        self.find(key)
+    }
+}
+
+/// Reads the saved file locations, then the aliases and web files listed after them.
+pub(crate) fn read_locations(
+    stream: &mut EncryptedDescriptor,
+    app_version: i32,
+) -> Result<FileLocations> {
+    let mut result = FileLocations::default();
+
+    let mut end_mark_found = false;
+    while !stream.is_done() {
+        let key = MediaKey {
+            first: stream.read_val()?,
+            second: stream.read_val()?,
+        };
+        let legacy_type = stream.read_val::<u32>()?;
+        let path = stream.read_val::<String>()?;
+        let bookmark = if app_version > 9013 {
+            stream.read_bytes()?
+        } else {
+            Vec::new()
+        };
+        let modified = stream.read_val()?;
+        let size = stream.read_val()?;
+
+        // an entry with nothing in it separates the locations from the aliases
+        if key.first == 0 && key.second == 0 && legacy_type == 0 && path.is_empty() && size == 0 {
+            end_mark_found = true;
+            break;
+        }
+
+        result.locations.push(FileLocation {
+            key,
+            path,
+            bookmark,
+            modified,
+            size,
+        });
+    }
+
+    if end_mark_found {
+        let count = stream.read_val::<u32>()?;
+        for _ in 0..count {
+            let (first, second, target_first, target_second) = stream.read_val()?;
+            let alias = MediaKey { first, second };
+            let target = MediaKey {
+                first: target_first,
+                second: target_second,
+            };
+            result.aliases.push((alias, target));
+        }
+
+        if !stream.is_done() {
+            let count = stream.read_val::<u32>()?;
+            for _ in 0..count {
+                result.web_files.push(WebFileLocation {
+                    url: stream.read_val()?,
+                    key: FileKey(stream.read_val()?),
+                    size: stream.read_val()?,
+                });
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::ValueSink;
+    use std::io::Write;
+
+    const APP_VERSION: i32 = 4_008_003;
+
+    fn write_entry(data: &mut EncryptedDescriptor, key: (u64, u64), path: &str, size: u32) {
+        data.write_val(&(key.0, key.1, 0u32, path.to_owned()))
+            .unwrap();
+        data.write_bytes(b"").unwrap();
+        data.write_val(&(2_460_000i64, 1_000u32)).unwrap();
+        data.write_all(&[1]).unwrap();
+        data.write_val(&size).unwrap();
+    }
+
+    #[test]
+    fn read_locations_aliases_and_web_files() {
+        let document = (u64::from(MediaKey::DOCUMENT) << 32 | 2, 42);
+        let alias = (u64::from(MediaKey::DOCUMENT) << 32 | 4, 42);
+
+        let mut data = EncryptedDescriptor::new();
+        write_entry(&mut data, document, "/downloads/file.pdf", 1234);
+        write_entry(&mut data, (0, 0), "", 0);
+        data.write_val(&1u32).unwrap();
+        data.write_val(&(alias.0, alias.1, document.0, document.1))
+            .unwrap();
+        data.write_val(&1u32).unwrap();
+        data.write_val(&(String::from("https://example.com/a.jpg"), 7u64, 99u32))
+            .unwrap();
+
+        let mut stream = data.reopen();
+        let result = read_locations(&mut stream, APP_VERSION).unwrap();
+        stream.should_be_done().unwrap();
+
+        assert_eq!(result.locations.len(), 1);
+        let location = &result.locations[0];
+        assert_eq!(location.path, "/downloads/file.pdf");
+        assert_eq!(location.size, 1234);
+        assert_eq!(location.modified.unix_time(), Some(1_677_196_801));
+
+        let alias_key = MediaKey {
+            first: alias.0,
+            second: alias.1,
+        };
+        assert_eq!(result.aliases.len(), 1);
+        assert_eq!(result.find(alias_key).unwrap().path, location.path);
+        assert_eq!(result.find_document(42).unwrap().size, 1234);
+
+        assert_eq!(
+            result.web_files,
+            [WebFileLocation {
+                url: String::from("https://example.com/a.jpg"),
+                key: FileKey(7),
+                size: 99,
+            }]
+        );
+    }
+
+    #[test]
+    fn read_locations_without_aliases() {
+        let mut data = EncryptedDescriptor::new();
+        write_entry(&mut data, (1, 2), "/downloads/file.pdf", 1234);
+        write_entry(&mut data, (0, 0), "", 0);
+        data.write_val(&0u32).unwrap();
+        data.write_val(&0u32).unwrap();
+
+        let mut stream = data.reopen();
+        let result = read_locations(&mut stream, APP_VERSION).unwrap();
+        stream.should_be_done().unwrap();
+        assert_eq!(result.locations.len(), 1);
+        assert!(result.aliases.is_empty());
+        assert!(result.web_files.is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::Read;
+
+use crate::data::{PeerId, UserId};
+use crate::descriptor::ValueStream;
+use crate::document::read_image_location;
+
+/// A user, as `Serialize::writePeer` stores it alongside the account's own data.
+#[derive(Debug, Clone)]
+pub struct StoredUser {
+    pub id: UserId,
+    pub photo_id: u64,
+    /// tdesktop's serialized `ImageLocation` for the user's profile photo.
+    pub photo_location: Vec<u8>,
+    pub first_name: String,
+    pub last_name: String,
+    pub phone: String,
+    pub username: String,
+    pub access_hash: u64,
+    /// `UserDataFlags`, which is zero for versions that didn't store them.
+    pub flags: i32,
+    /// The placeholder shown while typing an inline query, for inline bots.
+    pub inline_placeholder: String,
+    pub online_till: i32,
+    pub is_contact: bool,
+    /// -1 for anything but bots.
+    pub bot_info_version: i32,
+}
+
+/// The logged-in user, which the account map keeps a copy of.
+pub type SelfUser = StoredUser;
+
+impl StoredUser {
+    /// Reads a serialized peer written by `app_version`, which must be a user.
+    pub(crate) fn read(mut stream: impl Read, app_version: i32) -> std::io::Result<Self> {
+        let peer = stream.read_val::<PeerId>()?;
+        let id = peer.to_user().ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "peer is not a user")
+        })?;
+        let photo_id = stream.read_val()?;
+        let photo_location = read_image_location(&mut stream, app_version)?;
+
+        let first_name = stream.read_val()?;
+        let last_name = stream.read_val()?;
+        let phone = stream.read_val()?;
+        let username = stream.read_val()?;
+        let access_hash = stream.read_val()?;
+        let flags = if app_version >= 9012 {
+            stream.read_val()?
+        } else {
+            0
+        };
+
+        Ok(Self {
+            id,
+            photo_id,
+            photo_location,
+            first_name,
+            last_name,
+            phone,
+            username,
+            access_hash,
+            flags,
+            inline_placeholder: stream.read_val()?,
+            online_till: stream.read_val()?,
+            is_contact: stream.read_val::<i32>()? == 1,
+            bot_info_version: stream.read_val()?,
+        })
+    }
+}
@@ -0,0 +1,77 @@
+use crate::data::PeerId;
+use crate::descriptor::{EncryptedDescriptor, StreamWithEnd};
+use crate::error::{Error, Result};
+use crate::peer::StoredUser;
+
+/// The hashtags and inline bots suggested while typing and searching.
+#[derive(Debug, Clone, Default)]
+pub struct RecentHashtagsAndBots {
+    /// Hashtags used in messages, along with how often each was used.
+    pub write: Vec<(String, u16)>,
+    /// Hashtags searched for, along with how often each was searched.
+    pub search: Vec<(String, u16)>,
+    pub inline_bots: Vec<StoredUser>,
+}
+
+/// A bot the user has allowed to do things without asking again.
+#[derive(Debug, Copy, Clone)]
+pub struct TrustedBot {
+    pub bot: PeerId,
+    /// `PeerTrustFlags`, see the associated constants.
+    pub flags: u8,
+}
+
+impl TrustedBot {
+    pub const NO_OPEN_GAME: u8 = 1 << 0;
+    pub const PAYMENT: u8 = 1 << 1;
+    pub const OPEN_WEB_VIEW: u8 = 1 << 2;
+
+    /// Trusting a bot used to imply `NO_OPEN_GAME`, until the flags were split up in this version.
+    const SPLIT_FLAGS_VERSION: i32 = 2_003_004;
+}
+
+/// Reads the recent hashtags, and the recent inline bots in files that have them.
+pub(crate) fn read_recent_hashtags_and_bots(
+    stream: &mut EncryptedDescriptor,
+    app_version: i32,
+) -> Result<RecentHashtagsAndBots> {
+    let mut result = RecentHashtagsAndBots {
+        write: stream.read_val()?,
+        ..Default::default()
+    };
+    if !stream.is_done() {
+        result.search = stream.read_val()?;
+    }
+    if !stream.is_done() {
+        let count = stream.read_val::<u32>()?;
+        for _ in 0..count {
+            let location = stream.location();
+            let bot = StoredUser::read(&mut *stream, app_version)
+                .map_err(|e| Error::from_io(e, location))?;
+            result.inline_bots.push(bot);
+        }
+    }
+    Ok(result)
+}
+
+/// Reads the trusted bots along with what each one is trusted to do.
+pub(crate) fn read_trusted_bots(
+    stream: &mut EncryptedDescriptor,
+    app_version: i32,
+) -> Result<Vec<TrustedBot>> {
+    let count = stream.read_val::<i32>()?;
+    let mut result = Vec::new();
+    for _ in 0..count {
+        // the top 8 bits hold the flags, and the rest the serialized peer id
+        let value = stream.read_val::<u64>()?;
+        let mut flags = (value >> 56) as u8;
+        if app_version < TrustedBot::SPLIT_FLAGS_VERSION {
+            flags |= TrustedBot::NO_OPEN_GAME;
+        }
+        result.push(TrustedBot {
+            bot: PeerId::from_serialized(value & !(0xFF << 56)),
+            flags,
+        });
+    }
+    Ok(result)
+}
@@ -1,13 +1,18 @@
 use crate::descriptor::{Readable, ValueStream};
-use crate::FileKey;
+use crate::error::Error;
+use crate::{FileKey, MtpAuthKey};
 use num_enum::TryFromPrimitive;
 use std::convert::TryInto;
 use std::io::Read;
+use std::rc::Rc;
 
 #[non_exhaustive]
 pub enum Setting {
-    #[allow(dead_code)]
-    Key,
+    /// A per-DC auth key, as stored before `MtpAuthorization` replaced it.
+    Key {
+        dc_id: i32,
+        key: Rc<MtpAuthKey>,
+    },
     User {
         user_id: i32,
         dc_id: u32,
@@ -41,8 +46,12 @@ pub enum Setting {
     AnimationsDisabled(bool),
     ScalePercent(i32),
     LanguagesKey(FileKey),
-    #[allow(dead_code)]
-    CacheSettings,
+    CacheSettings {
+        size: i64,
+        time: i32,
+        size_big: i64,
+        time_big: i32,
+    },
     ApplicationSettings {
         serialized: Vec<u8>,
     },
@@ -77,13 +86,16 @@ impl Readable for Setting {
                 serialized: stream.read_bytes()?,
             },
 
+            Key => Setting::Key {
+                dc_id: stream.read_val()?,
+                key: stream.read_val()?,
+            },
+
             User => Setting::User {
                 user_id: stream.read_val()?,
                 dc_id: stream.read_val()?,
             },
 
-            Key => todo!(),
-
             MtpAuthorization => Setting::MtpAuthorization {
                 serialized: stream.read_bytes()?,
             },
@@ -93,13 +105,12 @@ impl Readable for Setting {
             SendToMenu => Setting::SendToMenu(sbool!()),
             UseExternalVideoPlayer => Setting::UseExternalVideoPlayer(sbool!()),
 
-            CacheSettings => {
-                let _size = stream.read_val::<i64>()?;
-                let _time = stream.read_val::<i32>()?;
-                let _size_big = stream.read_val::<i64>()?;
-                let _time_big = stream.read_val::<i32>()?;
-                todo!()
-            }
+            CacheSettings => Setting::CacheSettings {
+                size: stream.read_val()?,
+                time: stream.read_val()?,
+                size_big: stream.read_val()?,
+                time_big: stream.read_val()?,
+            },
 
             AnimationsDisabled => Setting::AnimationsDisabled(sbool!()),
 
@@ -135,7 +146,14 @@ impl Readable for Setting {
             DialogLastPath => Setting::DialogLastPath(stream.read_bytes()?),
             FallbackProductionConfig => Setting::FallbackProductionConfig(stream.read_bytes()?),
 
-            k => todo!("{:?}", k),
+            // the location is filled in by whichever descriptor we're being read from
+            kind => {
+                return Err(Error::UnsupportedSetting {
+                    location: Default::default(),
+                    kind,
+                }
+                .into())
+            }
         };
         Ok(setting)
     }
@@ -0,0 +1,192 @@
+use std::io::Write;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::rc::Rc;
+
+use byteorder::{WriteBytesExt, BE, LE};
+
+use crate::authorization::MtpAuthorization;
+use crate::data::UserId;
+use crate::MtpAuthKey;
+
+/// The production DCs, as hardcoded in tdesktop and most other clients.
+const PRODUCTION_DCS: [(i32, Ipv4Addr); 5] = [
+    (1, Ipv4Addr::new(149, 154, 175, 53)),
+    (2, Ipv4Addr::new(149, 154, 167, 51)),
+    (3, Ipv4Addr::new(149, 154, 175, 100)),
+    (4, Ipv4Addr::new(149, 154, 167, 91)),
+    (5, Ipv4Addr::new(91, 108, 56, 130)),
+];
+const PRODUCTION_PORT: u16 = 443;
+
+/// The address of a production DC, for when the caller doesn't know of a better one.
+pub fn default_dc_address(dc_id: i32) -> Option<SocketAddr> {
+    PRODUCTION_DCS
+        .iter()
+        .find(|(id, _)| *id == dc_id)
+        .map(|&(_, ip)| SocketAddr::new(ip.into(), PRODUCTION_PORT))
+}
+
+/// Everything an MTProto client needs to resume an account's session on its main DC.
+#[derive(Debug, Clone)]
+pub struct SessionExport {
+    pub user_id: UserId,
+    pub dc_id: i32,
+    pub address: SocketAddr,
+    pub auth_key: Rc<MtpAuthKey>,
+}
+
+impl SessionExport {
+    /// Picks out the main DC and its key, using the default address for that DC.
+    ///
+    /// Returns `None` if the main DC has no key, or isn't a production DC.
+    pub fn from_authorization(auth: &MtpAuthorization) -> Option<Self> {
+        Some(Self {
+            user_id: auth.user_id,
+            dc_id: auth.main_dc_id,
+            address: default_dc_address(auth.main_dc_id)?,
+            auth_key: Rc::clone(auth.main_key()?),
+        })
+    }
+
+    /// Encodes the session as a Telethon `StringSession`.
+    pub fn to_telethon_string(&self) -> String {
+        let mut packed = Vec::new();
+        packed.push(self.dc_id as u8);
+        match self.address.ip() {
+            IpAddr::V4(ip) => packed.extend_from_slice(&ip.octets()),
+            IpAddr::V6(ip) => packed.extend_from_slice(&ip.octets()),
+        }
+        packed.write_u16::<BE>(self.address.port()).unwrap();
+        packed.extend_from_slice(self.auth_key.data());
+
+        // the leading character is the format version
+        format!("1{}", base64::encode_config(packed, base64::URL_SAFE))
+    }
+
+    /// Encodes the session as a Pyrogram session string.
+    ///
+    /// Pyrogram doesn't store the DC's address, but does store the `api_id` the session belongs to,
+    /// which is 2040 for sessions created by Telegram Desktop itself.
+    pub fn to_pyrogram_string(&self, api_id: i32) -> String {
+        let mut packed = Vec::new();
+        packed.push(self.dc_id as u8);
+        packed.write_i32::<BE>(api_id).unwrap();
+        packed.push(false as u8); // test mode
+        packed.extend_from_slice(self.auth_key.data());
+        packed.write_u64::<BE>(self.user_id.bare).unwrap();
+        packed.push(false as u8); // is bot
+
+        base64::encode_config(packed, base64::URL_SAFE_NO_PAD)
+    }
+
+    /// Serializes the session in the format grammers' `Session::save` writes to disk.
+    pub fn to_grammers_session(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_grammers_session(&mut out, self).unwrap();
+        out
+    }
+}
+
+// session flags:# dcs:Vector<DataCenter> user:flags.0?User state:flags.1?UpdateState = Session;
+const GRAMMERS_SESSION: u32 = 0xa73eb8ce;
+// dataCenter flags:# id:int ipv4:flags.0?int ipv6:flags.1?int128 port:int auth:flags.2?bytes = DataCenter;
+const GRAMMERS_DATA_CENTER: u32 = 0x873e88db;
+// user id:long dc:int bot:Bool = User;
+const GRAMMERS_USER: u32 = 0x672f428d;
+const TL_VECTOR: u32 = 0x1cb5c415;
+const TL_BOOL_FALSE: u32 = 0xbc799737;
+
+fn write_grammers_session(mut out: impl Write, session: &SessionExport) -> std::io::Result<()> {
+    out.write_u32::<LE>(GRAMMERS_SESSION)?;
+    out.write_u32::<LE>(1 << 0)?; // only the user is present
+
+    out.write_u32::<LE>(TL_VECTOR)?;
+    out.write_i32::<LE>(1)?;
+    out.write_u32::<LE>(GRAMMERS_DATA_CENTER)?;
+    match session.address.ip() {
+        IpAddr::V4(ip) => {
+            out.write_u32::<LE>(1 << 0 | 1 << 2)?;
+            out.write_i32::<LE>(session.dc_id)?;
+            // grammers packs the octets into its int little-endian, so they end up as-is
+            out.write_all(&ip.octets())?;
+        }
+        IpAddr::V6(ip) => {
+            out.write_u32::<LE>(1 << 1 | 1 << 2)?;
+            out.write_i32::<LE>(session.dc_id)?;
+            out.write_all(&ip.octets())?;
+        }
+    }
+    out.write_i32::<LE>(session.address.port().into())?;
+    write_tl_bytes(&mut out, session.auth_key.data())?;
+
+    out.write_u32::<LE>(GRAMMERS_USER)?;
+    out.write_u64::<LE>(session.user_id.bare)?;
+    out.write_i32::<LE>(session.dc_id)?;
+    out.write_u32::<LE>(TL_BOOL_FALSE)?;
+
+    Ok(())
+}
+
+fn write_tl_bytes(mut out: impl Write, bytes: &[u8]) -> std::io::Result<()> {
+    let header_len = if bytes.len() < 254 {
+        out.write_u8(bytes.len() as u8)?;
+        1
+    } else {
+        out.write_u8(254)?;
+        out.write_u24::<LE>(bytes.len() as u32)?;
+        4
+    };
+    out.write_all(bytes)?;
+    let padding = (4 - (header_len + bytes.len()) % 4) % 4;
+    out.write_all(&[0; 3][..padding])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::descriptor::Readable;
+
+    fn session() -> SessionExport {
+        let key = (0..=255).collect::<Vec<u8>>();
+        SessionExport {
+            user_id: UserId::new(5_000_000_000),
+            dc_id: 2,
+            address: default_dc_address(2).unwrap(),
+            auth_key: Rc::<MtpAuthKey>::read_from(&key[..]).unwrap(),
+        }
+    }
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn telethon_string() {
+        assert_eq!(
+            session().to_telethon_string(),
+            "1ApWapzMBuwABAgMEBQYHCAkKCwwNDg8QERITFBUWFxgZGhscHR4fICEiIyQlJicoKSorLC0uLzAxMjM0NTY3ODk6Ozw9Pj9AQUJDREVGR0hJSktMTU5PUFFSU1RVVldYWVpbXF1eX2BhYmNkZWZnaGlqa2xtbm9wcXJzdHV2d3h5ent8fX5_gIGCg4SFhoeIiYqLjI2Oj5CRkpOUlZaXmJmam5ydnp-goaKjpKWmp6ipqqusra6vsLGys7S1tre4ubq7vL2-v8DBwsPExcbHyMnKy8zNzs_Q0dLT1NXW19jZ2tvc3d7f4OHi4-Tl5ufo6err7O3u7_Dx8vP09fb3-Pn6-_z9_v8="
+        );
+    }
+
+    #[test]
+    fn pyrogram_string() {
+        assert_eq!(
+            session().to_pyrogram_string(2040),
+            "AgAAB_gAAAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8gISIjJCUmJygpKissLS4vMDEyMzQ1Njc4OTo7PD0-P0BBQkNERUZHSElKS0xNTk9QUVJTVFVWV1hZWltcXV5fYGFiY2RlZmdoaWprbG1ub3BxcnN0dXZ3eHl6e3x9fn-AgYKDhIWGh4iJiouMjY6PkJGSk5SVlpeYmZqbnJ2en6ChoqOkpaanqKmqq6ytrq-wsbKztLW2t7i5uru8vb6_wMHCw8TFxsfIycrLzM3Oz9DR0tPU1dbX2Nna29zd3t_g4eLj5OXm5-jp6uvs7e7v8PHy8_T19vf4-fr7_P3-_wAAAAEqBfIAAA"
+        );
+    }
+
+    #[test]
+    fn grammers_session() {
+        // session, flags, a vector of one data center with its ipv4 address, port and 256-byte key
+        let mut expected =
+            hex("ceb83ea70100000015c4b51c01000000db883e870500000002000000959aa733bb010000fe000100");
+        expected.extend(0..=255);
+        // the user, its DC and boolFalse for not being a bot
+        expected.extend(hex("8d422f6700f2052a0100000002000000379779bc"));
+        assert_eq!(session().to_grammers_session(), expected);
+    }
+}
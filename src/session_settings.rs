@@ -0,0 +1,368 @@
+use crate::data::{DocumentId, MsgId, PeerId};
+use crate::descriptor::{at_end, parse_blob, read_bool, read_list, Blob, ValueStream};
+use crate::error::{Location, Result};
+
+/// The per-account settings tdesktop keeps in `Main::SessionSettings`.
+///
+/// Fields that weren't present in the serialized data are left at their defaults.
+#[derive(Debug, Default)]
+pub struct SessionSettings {
+    /// The version of Telegram Desktop that wrote the settings file.
+    pub app_version: i32,
+    /// The version of the serialized settings themselves, which is 0 for untagged data.
+    pub version: i32,
+    pub selector_tab: i32,
+    pub group_stickers_section_hidden: Vec<PeerId>,
+    pub support_switch: i32,
+    pub support_fix_chats_order: bool,
+    pub support_templates_autocomplete: bool,
+    pub support_chats_time_slice: i32,
+    /// `Data::AutoDownload::Settings`, still in its own serialized form.
+    pub auto_download: Vec<u8>,
+    pub support_all_search_results: bool,
+    pub archive_collapsed: bool,
+    pub archive_in_main_menu: bool,
+    pub skip_archive_in_search: bool,
+    pub media_last_playback_position: Vec<(DocumentId, i64)>,
+    pub hidden_pinned_messages: Vec<HiddenPinnedMessage>,
+    pub dialogs_filters_enabled: bool,
+    pub support_all_silent: bool,
+    pub photo_editor_hint_shows_count: i32,
+    pub mute_periods: Vec<u64>,
+    pub last_non_premium_limit_download: i32,
+    pub last_non_premium_limit_upload: i32,
+    pub group_emoji_section_hidden: Vec<PeerId>,
+    /// Settings that have since moved to the application-wide settings.
+    ///
+    /// These are only stored by versions before 2.
+    pub legacy: Option<LegacySessionSettings>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct HiddenPinnedMessage {
+    pub peer: PeerId,
+    /// Zero unless the message was pinned in a forum topic.
+    pub topic_root_id: MsgId,
+    pub msg_id: MsgId,
+}
+
+#[derive(Debug, Default)]
+pub struct LegacySessionSettings {
+    pub last_seen_warning_seen: bool,
+    pub sound_overrides: Vec<(String, String)>,
+    pub tabbed_selector_section_enabled: Option<bool>,
+    pub float_player_column: Option<i32>,
+    pub float_player_corner: Option<i32>,
+    pub third_section_info_enabled: Option<bool>,
+    pub small_dialogs_list: Option<bool>,
+    /// Stored as millionths.
+    pub dialogs_width_ratio: Option<i32>,
+    pub third_column_width: Option<i32>,
+    pub third_section_extended_by: Option<i32>,
+    pub send_files_way: Option<i32>,
+    pub calls_peer_to_peer: Option<i32>,
+    pub send_submit_way: Option<i32>,
+    pub include_muted_counter: Option<bool>,
+    pub count_unread_messages: Option<bool>,
+    pub exe_launch_warning: Option<bool>,
+    pub notify_about_pinned: Option<bool>,
+    pub autoplay_gifs: Option<bool>,
+    pub loop_animated_stickers: Option<bool>,
+    pub large_emoji: Option<bool>,
+    pub replace_emoji: Option<bool>,
+    pub suggest_emoji: Option<bool>,
+    pub suggest_stickers_by_emoji: Option<bool>,
+    pub spellchecker_enabled: Option<bool>,
+    pub video_playback_speed: Option<i32>,
+    pub video_pip_geometry: Option<Vec<u8>>,
+    pub dictionaries_enabled: Vec<i64>,
+    pub auto_download_dictionaries: Option<bool>,
+}
+
+const VERSION_TAG: i32 = -1;
+
+impl SessionSettings {
+    /// Parses the blob stored in a `SessionSettings` setting, which was read from `location`.
+    pub(crate) fn from_serialized(
+        serialized: &[u8],
+        app_version: i32,
+        location: Location,
+    ) -> Result<Self> {
+        parse_blob(serialized, location, |stream| {
+            Self::read(stream, app_version)
+        })
+    }
+
+    fn read(stream: &mut Blob<'_>, app_version: i32) -> std::io::Result<Self> {
+        let mut result = Self {
+            app_version,
+            ..Default::default()
+        };
+
+        let version_tag = stream.read_val::<i32>()?;
+        if version_tag == VERSION_TAG {
+            result.version = stream.read_val()?;
+        }
+
+        if result.version < 2 {
+            let last_seen_warning_seen = if version_tag == VERSION_TAG {
+                read_bool(&mut *stream)?
+            } else {
+                version_tag == 1
+            };
+            let mut legacy = LegacySessionSettings {
+                last_seen_warning_seen,
+                ..Default::default()
+            };
+            result.read_legacy(stream, &mut legacy)?;
+            result.legacy = Some(legacy);
+        } else {
+            result.selector_tab = stream.read_val()?;
+            result.group_stickers_section_hidden = read_list(stream, |s| s.read_val())?;
+            result.support_switch = stream.read_val()?;
+            result.support_fix_chats_order = read_bool(&mut *stream)?;
+            result.support_templates_autocomplete = read_bool(&mut *stream)?;
+            result.support_chats_time_slice = stream.read_val()?;
+            result.auto_download = stream.read_bytes()?;
+            result.support_all_search_results = read_bool(&mut *stream)?;
+            result.archive_collapsed = read_bool(&mut *stream)?;
+            result.archive_in_main_menu = read_bool(&mut *stream)?;
+            result.skip_archive_in_search = read_bool(&mut *stream)?;
+            result.media_last_playback_position = read_list(stream, |s| s.read_val())?;
+            result.hidden_pinned_messages = read_list(stream, |s| {
+                Ok(HiddenPinnedMessage {
+                    peer: s.read_val()?,
+                    topic_root_id: 0,
+                    msg_id: s.read_val::<i32>()?.into(),
+                })
+            })?;
+            result.dialogs_filters_enabled = read_bool(&mut *stream)?;
+            result.support_all_silent = read_bool(&mut *stream)?;
+            result.photo_editor_hint_shows_count = stream.read_val()?;
+        }
+
+        if !at_end(stream) {
+            // supersedes the old list of hidden pinned messages, now with forum topics;
+            // `serialize` writes the pinned message id before the topic root id of its key
+            let hidden = read_list(stream, |s| {
+                Ok(HiddenPinnedMessage {
+                    peer: s.read_val()?,
+                    msg_id: s.read_val()?,
+                    topic_root_id: s.read_val()?,
+                })
+            })?;
+            if !hidden.is_empty() {
+                result.hidden_pinned_messages = hidden;
+            }
+        }
+        if !at_end(stream) {
+            result.mute_periods = read_list(stream, |s| s.read_val())?;
+        }
+        if !at_end(stream) {
+            result.last_non_premium_limit_download = stream.read_val()?;
+            result.last_non_premium_limit_upload = stream.read_val()?;
+        }
+        if !at_end(stream) {
+            result.group_emoji_section_hidden = read_list(stream, |s| s.read_val())?;
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the pre-version-2 layout, where every field after the first is optional.
+    fn read_legacy(
+        &mut self,
+        stream: &mut Blob<'_>,
+        legacy: &mut LegacySessionSettings,
+    ) -> std::io::Result<()> {
+        macro_rules! optional {
+            ($($body:tt)*) => {
+                if at_end(stream) {
+                    return Ok(());
+                }
+                $($body)*
+            };
+        }
+
+        optional! {
+            self.selector_tab = stream.read_val()?;
+        }
+        optional! {
+            legacy.sound_overrides = read_list(stream, |s| s.read_val())?;
+        }
+        optional! {
+            legacy.tabbed_selector_section_enabled = Some(read_bool(&mut *stream)?);
+        }
+        optional! {
+            legacy.float_player_column = Some(stream.read_val()?);
+        }
+        optional! {
+            legacy.float_player_corner = Some(stream.read_val()?);
+        }
+        optional! {
+            self.group_stickers_section_hidden = read_list(stream, |s| s.read_val())?;
+        }
+        optional! {
+            legacy.third_section_info_enabled = Some(read_bool(&mut *stream)?);
+            legacy.small_dialogs_list = Some(read_bool(&mut *stream)?);
+        }
+        optional! {
+            legacy.dialogs_width_ratio = Some(stream.read_val()?);
+            legacy.third_column_width = Some(stream.read_val()?);
+            legacy.third_section_extended_by = Some(stream.read_val()?);
+        }
+        optional! {
+            legacy.send_files_way = Some(stream.read_val()?);
+        }
+        optional! {
+            legacy.calls_peer_to_peer = Some(stream.read_val()?);
+        }
+        optional! {
+            legacy.send_submit_way = Some(stream.read_val()?);
+            self.support_switch = stream.read_val()?;
+            self.support_fix_chats_order = read_bool(&mut *stream)?;
+        }
+        optional! {
+            self.support_templates_autocomplete = read_bool(&mut *stream)?;
+        }
+        optional! {
+            self.support_chats_time_slice = stream.read_val()?;
+        }
+        optional! {
+            legacy.include_muted_counter = Some(read_bool(&mut *stream)?);
+            legacy.count_unread_messages = Some(read_bool(&mut *stream)?);
+        }
+        optional! {
+            legacy.exe_launch_warning = Some(read_bool(&mut *stream)?);
+        }
+        optional! {
+            self.auto_download = stream.read_bytes()?;
+        }
+        optional! {
+            self.support_all_search_results = read_bool(&mut *stream)?;
+        }
+        optional! {
+            self.archive_collapsed = read_bool(&mut *stream)?;
+        }
+        optional! {
+            legacy.notify_about_pinned = Some(read_bool(&mut *stream)?);
+        }
+        optional! {
+            self.archive_in_main_menu = read_bool(&mut *stream)?;
+        }
+        optional! {
+            self.skip_archive_in_search = read_bool(&mut *stream)?;
+        }
+        optional! {
+            legacy.autoplay_gifs = Some(read_bool(&mut *stream)?);
+            legacy.loop_animated_stickers = Some(read_bool(&mut *stream)?);
+            legacy.large_emoji = Some(read_bool(&mut *stream)?);
+            legacy.replace_emoji = Some(read_bool(&mut *stream)?);
+            legacy.suggest_emoji = Some(read_bool(&mut *stream)?);
+            legacy.suggest_stickers_by_emoji = Some(read_bool(&mut *stream)?);
+        }
+        optional! {
+            legacy.spellchecker_enabled = Some(read_bool(&mut *stream)?);
+        }
+        optional! {
+            self.media_last_playback_position = read_list(stream, |s| s.read_val())?;
+        }
+        optional! {
+            legacy.video_playback_speed = Some(stream.read_val()?);
+        }
+        optional! {
+            legacy.video_pip_geometry = Some(stream.read_bytes()?);
+        }
+        optional! {
+            legacy.dictionaries_enabled = read_list(stream, |s| s.read_val())?;
+        }
+        optional! {
+            legacy.auto_download_dictionaries = Some(read_bool(&mut *stream)?);
+        }
+        optional! {
+            self.hidden_pinned_messages = read_list(stream, |s| {
+                Ok(HiddenPinnedMessage {
+                    peer: s.read_val()?,
+                    topic_root_id: 0,
+                    msg_id: s.read_val::<i32>()?.into(),
+                })
+            })?;
+        }
+        optional! {
+            self.dialogs_filters_enabled = read_bool(&mut *stream)?;
+        }
+        optional! {
+            self.support_all_silent = read_bool(&mut *stream)?;
+        }
+        optional! {
+            self.photo_editor_hint_shows_count = stream.read_val()?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::ChannelId;
+    use crate::descriptor::ValueSink;
+
+    const APP_VERSION: i32 = 4_008_003;
+
+    /// Everything `serialize` writes before the list of hidden pinned messages with topics.
+    fn write_version_2(data: &mut Vec<u8>, old_hidden: &[(u64, i32)]) {
+        data.write_val(&(VERSION_TAG, 2i32, 3i32, 0i32)).unwrap();
+        data.write_val(&(0i32, 0i32, 1i32, 0i32)).unwrap();
+        data.write_bytes(b"").unwrap();
+        data.write_val(&(0i32, 1i32, 0i32, 0i32)).unwrap();
+        data.write_val(&0i32).unwrap();
+        data.write_val(&(old_hidden.len() as i32)).unwrap();
+        for &(peer, msg_id) in old_hidden {
+            data.write_val(&(peer, msg_id)).unwrap();
+        }
+        data.write_val(&(1i32, 0i32, 5i32)).unwrap();
+    }
+
+    #[test]
+    fn read_hidden_pinned_messages_with_topics() {
+        let channel = PeerId::from_chat_id(ChannelId::new(1_234_567));
+        let mut data = Vec::new();
+        write_version_2(&mut data, &[]);
+        data.write_val(&1i32).unwrap();
+        data.write_val(&(channel.serialize(), 100i64, 7i64))
+            .unwrap();
+        data.write_val(&(0i32, 0i32, 0i32, 0i32)).unwrap();
+
+        let result =
+            SessionSettings::from_serialized(&data, APP_VERSION, Location::default()).unwrap();
+        assert_eq!(result.version, 2);
+        assert_eq!(result.selector_tab, 3);
+        assert!(result.support_templates_autocomplete);
+        assert!(result.archive_collapsed);
+        assert!(result.dialogs_filters_enabled);
+        assert_eq!(result.photo_editor_hint_shows_count, 5);
+        assert!(result.legacy.is_none());
+
+        let hidden = &result.hidden_pinned_messages;
+        assert_eq!(hidden.len(), 1);
+        assert_eq!(hidden[0].peer, channel);
+        assert_eq!(hidden[0].msg_id, 100);
+        assert_eq!(hidden[0].topic_root_id, 7);
+    }
+
+    #[test]
+    fn read_old_hidden_pinned_messages() {
+        let channel = PeerId::from_chat_id(ChannelId::new(1_234_567));
+        let mut data = Vec::new();
+        write_version_2(&mut data, &[(channel.serialize(), 100)]);
+
+        let result =
+            SessionSettings::from_serialized(&data, APP_VERSION, Location::default()).unwrap();
+        let hidden = &result.hidden_pinned_messages;
+        assert_eq!(hidden.len(), 1);
+        assert_eq!(hidden[0].peer, channel);
+        assert_eq!(hidden[0].msg_id, 100);
+        assert_eq!(hidden[0].topic_root_id, 0);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::data::DocumentId;
+use crate::descriptor::{EncryptedDescriptor, StreamWithEnd};
+use crate::document::StoredDocument;
+use crate::error::{Error, Result};
+
+const STICKERS_VERSION_TAG: u32 = u32::MAX;
+const STICKERS_SERIALIZE_VERSION: i32 = 2;
+
+/// The sticker set files an account keeps, as listed in its map.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StickerSetsFile {
+    Installed,
+    Featured,
+    Recent,
+    Archived,
+    InstalledMasks,
+    RecentMasks,
+    ArchivedMasks,
+    /// The user's favorite stickers, stored as a single special set.
+    Faved,
+}
+
+/// A sticker set, as `Data::StickersSet` is written to disk.
+#[derive(Debug, Clone)]
+pub struct StickerSet {
+    pub id: u64,
+    pub access_hash: u64,
+    pub hash: u64,
+    pub short_name: String,
+    pub title: String,
+    /// Negative for sets that were never loaded, in which case `documents` is empty.
+    pub count: i32,
+    /// `Data::StickersSetFlags`.
+    pub flags: i32,
+    pub install_date: i32,
+    /// tdesktop's serialized `ImageLocation` for the set's thumbnail.
+    pub thumbnail: Vec<u8>,
+    pub documents: Vec<StoredDocument>,
+    /// When each document was added, for sets that keep track of it.
+    pub dates: Vec<i32>,
+    /// Which of the set's documents stand for each emoji.
+    pub emoji: Vec<(String, Vec<DocumentId>)>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StickerSets {
+    pub sets: Vec<StickerSet>,
+    /// The ids of the sets, in the order the user arranged them.
+    pub order: Vec<u64>,
+}
+
+fn read_set(stream: &mut EncryptedDescriptor, app_version: i32) -> Result<StickerSet> {
+    let mut set = StickerSet {
+        id: stream.read_val()?,
+        access_hash: stream.read_val()?,
+        hash: stream.read_val()?,
+        short_name: stream.read_val()?,
+        title: stream.read_val()?,
+        count: stream.read_val()?,
+        flags: stream.read_val()?,
+        install_date: stream.read_val()?,
+        thumbnail: stream.read_bytes()?,
+        documents: Vec::new(),
+        dates: Vec::new(),
+        emoji: Vec::new(),
+    };
+
+    // sets that were never loaded are written without their contents
+    if set.count < 0 {
+        return Ok(set);
+    }
+
+    for _ in 0..set.count {
+        set.documents
+            .push(StoredDocument::read(stream, app_version)?);
+    }
+
+    let dates_count = stream.read_val::<i32>()?;
+    for _ in 0..dates_count {
+        set.dates.push(stream.read_val()?);
+    }
+
+    let emoji_count = stream.read_val::<i32>()?;
+    for _ in 0..emoji_count {
+        let emoji = stream.read_val()?;
+        let stickers = stream.read_val::<Vec<DocumentId>>()?;
+        set.emoji.push((emoji, stickers));
+    }
+
+    Ok(set)
+}
+
+/// Reads a list of sticker sets, along with the order they were arranged in.
+pub(crate) fn read_sticker_sets(
+    stream: &mut EncryptedDescriptor,
+    app_version: i32,
+) -> Result<StickerSets> {
+    let location = stream.location();
+    let version_tag = stream.read_val::<u32>()?;
+    let version = stream.read_val::<i32>()?;
+    if version_tag != STICKERS_VERSION_TAG || version != STICKERS_SERIALIZE_VERSION {
+        // tdesktop discards these too, and downloads the sets again
+        return Err(Error::invalid_data(
+            location,
+            "outdated sticker sets format",
+        ));
+    }
+
+    let mut result = StickerSets::default();
+    let count = stream.read_val::<i32>()?;
+    for _ in 0..count {
+        result.sets.push(read_set(stream, app_version)?);
+    }
+
+    if !stream.is_done() {
+        result.order = stream.read_val()?;
+    }
+
+    Ok(result)
+}
@@ -0,0 +1,272 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+use zip::write::{FileOptions, ZipWriter};
+use zip::ZipArchive;
+
+use crate::background::ThemeBackground;
+use crate::data::{DocumentId, UserId};
+use crate::descriptor::FileReadDescriptor;
+use crate::error::{Error, Location, Result};
+use crate::legacy_media::MediaType;
+use crate::palette::Palette;
+use crate::{FileKey, MtpAuthKey};
+
+#[derive(Debug, Default)]
+pub struct CloudTheme {
+    pub id: u64,
+    pub access_hash: u64,
+    pub slug: String,
+    pub title: String,
+    pub document_id: DocumentId,
+    pub created_by: UserId,
+    pub users_count: i32,
+}
+
+#[derive(Debug, Default)]
+pub struct ThemeObject {
+    pub path_relative: String,
+    pub path_absolute: String,
+    pub content: Vec<u8>,
+    pub cloud: CloudTheme,
+}
+
+#[derive(Debug, Default)]
+pub struct CachedTheme {
+    pub colors: Vec<u8>,
+    pub background: Vec<u8>,
+    pub tiled: bool,
+    pub palette_checksum: u32,
+    pub content_checksum: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct SavedTheme {
+    pub object: ThemeObject,
+    pub cache: CachedTheme,
+    /// Where the theme was read from.
+    pub(crate) location: Location,
+}
+
+const THEME_NEW_PATH_RELATIVE_TAG: &str = "special://new_tag";
+const THEME_FILE_SIZE_LIMIT: u64 = 5 * 1024 * 1024;
+const THEME_COLORS_NAME: &str = "colors.tdesktop-theme";
+
+pub(crate) fn read_theme_using_key(
+    key: FileKey,
+    auth_key: &MtpAuthKey,
+    base_path: &Path,
+) -> Result<SavedTheme> {
+    let mut theme_data = FileReadDescriptor::open(key.to_file_part(), base_path)?;
+    let location = theme_data.location();
+    let mut theme = theme_data.read_encrypted(auth_key)?;
+
+    let mut result = SavedTheme {
+        location,
+        ..Default::default()
+    };
+    let (object, cache) = (&mut result.object, &mut result.cache);
+    object.content = theme.read_bytes()?;
+    // errors about the theme's own file point at where its path is stored
+    let path_location = theme.location();
+    let tag = theme.read_val::<String>()?;
+    object.path_absolute = theme.read_val()?;
+
+    let field1: i32;
+
+    let is_new_tag = tag == THEME_NEW_PATH_RELATIVE_TAG;
+    if is_new_tag {
+        object.path_relative = theme.read_val()?;
+        object.cloud.id = theme.read_val()?;
+        object.cloud.access_hash = theme.read_val()?;
+        object.cloud.slug = theme.read_val()?;
+        object.cloud.title = theme.read_val()?;
+        object.cloud.document_id = theme.read_val()?;
+        field1 = theme.read_val()?;
+    } else {
+        object.path_relative = tag;
+        field1 = 0;
+    }
+
+    let mut ignore_cache = false;
+    if object.cloud.id == 0 {
+        let rel = &object.path_relative;
+        let path = if !rel.is_empty() && Path::new(rel).exists() {
+            rel
+        } else {
+            &object.path_absolute
+        };
+
+        let io_error = |e| Error::from_io(e, path_location.clone());
+
+        let mut file = File::open(path).map_err(io_error)?;
+        let len = file.metadata().map_err(io_error)?.len();
+        if len >= THEME_FILE_SIZE_LIMIT {
+            return Err(Error::ThemeTooLarge {
+                location: path_location,
+                size: len,
+            });
+        }
+
+        let mut file_content = Vec::with_capacity(len as usize);
+        file.read_to_end(&mut file_content).map_err(io_error)?;
+        if object.content != file_content {
+            object.content = file_content;
+            ignore_cache = true;
+        }
+    }
+
+    let cache_palette_checksum = theme.read_val::<u32>()?;
+    let cache_content_checksum = theme.read_val::<u32>()?;
+    let cache_colors = theme.read_bytes()?;
+    let cache_background = theme.read_bytes()?;
+    let field2 = theme.read_val::<u32>()?;
+
+    if !ignore_cache {
+        *cache = CachedTheme {
+            palette_checksum: cache_palette_checksum,
+            content_checksum: cache_content_checksum,
+            colors: cache_colors,
+            background: cache_background,
+            tiled: field2 & 0xFF == 1,
+        }
+    }
+
+    if is_new_tag {
+        object.cloud.created_by.bare = ((field2 as u64 >> 8) << 32) | field1 as u64;
+    }
+
+    Ok(result)
+}
+
+pub(crate) fn read_editing_palette(base_path: &Path) -> Option<Vec<u8>> {
+    let mut file = File::open(editing_palette_path(base_path)).ok()?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).ok()?;
+    Some(buf)
+}
+
+fn editing_palette_path(base_path: &Path) -> std::path::PathBuf {
+    base_path.join("editing-theme.tdesktop-palette")
+}
+
+/// `QDataStream` writes this before an image that isn't null.
+const QIMAGE_NOT_NULL: [u8; 4] = [0, 0, 0, 1];
+
+impl SavedTheme {
+    fn validate_cache(&self) -> Result<()> {
+        if self.cache.palette_checksum != Palette::CHECKSUM {
+            return Err(self.invalid_cache("palette checksum mismatch"));
+        }
+
+        if self.cache.content_checksum != crczoo::crc32(&self.object.content) {
+            return Err(self.invalid_cache("content checksum mismatch"));
+        }
+
+        Ok(())
+    }
+
+    fn invalid_cache(&self, reason: &'static str) -> Error {
+        Error::InvalidCache {
+            location: self.location.clone(),
+            reason,
+        }
+    }
+
+    /// Loads the palette from the cached colors, provided the cache is still valid.
+    pub fn palette_from_cache(&self) -> Result<Box<Palette>> {
+        self.validate_cache()?;
+
+        const PALETTE_SIZE: usize = std::mem::size_of::<Palette>();
+        let color_data: Box<[u8; PALETTE_SIZE]> = self
+            .cache
+            .colors
+            .clone()
+            .into_boxed_slice()
+            .try_into()
+            .map_err(|_| self.invalid_cache("bad palette data size"))?;
+        Ok(Palette::load_from_cache(color_data))
+    }
+
+    /// The PNG of the theme's own background from the cache, provided the cache is still valid.
+    ///
+    /// Themes without a background of their own yield nothing.
+    pub fn background_from_cache(&self) -> Result<Option<&[u8]>> {
+        self.validate_cache()?;
+
+        let background = &self.cache.background;
+        if background.is_empty() || background[..] == [0; 4] {
+            return Ok(None);
+        }
+        match background.strip_prefix(&QIMAGE_NOT_NULL[..]) {
+            Some(png) => Ok(Some(png)),
+            None => Err(self.invalid_cache("bad background image")),
+        }
+    }
+
+    /// The palette the theme was made from, as the text of a `.tdesktop-palette`.
+    ///
+    /// This comes from the theme's content where possible, and from the cached colors otherwise.
+    pub fn palette_text(&self) -> Result<Vec<u8>> {
+        let content = &self.object.content;
+        if !content.starts_with(b"PK") {
+            return Ok(content.clone());
+        }
+
+        let from_archive = || -> zip::result::ZipResult<Vec<u8>> {
+            let mut archive = ZipArchive::new(Cursor::new(&content[..]))?;
+            let mut colors = archive.by_name(THEME_COLORS_NAME)?;
+            let mut buf = Vec::new();
+            colors.read_to_end(&mut buf)?;
+            Ok(buf)
+        };
+        match from_archive() {
+            Ok(colors) => Ok(colors),
+            Err(_) => Ok(self.palette_from_cache()?.to_string().into_bytes()),
+        }
+    }
+
+    /// Writes the theme to `path` as a `.tdesktop-theme` archive, along with `background` if it has a PNG or JPEG image.
+    ///
+    /// Errors are reported at the theme's location in tdata, since `path` lies outside of it.
+    pub fn export_archive(
+        &self,
+        background: Option<&ThemeBackground>,
+        path: impl AsRef<Path>,
+    ) -> Result<()> {
+        let colors = self.palette_text()?;
+
+        let path = path.as_ref();
+        let write = || -> zip::result::ZipResult<()> {
+            let mut archive = ZipWriter::new(File::create(path)?);
+            let options = FileOptions::default();
+
+            archive.start_file(THEME_COLORS_NAME, options)?;
+            archive.write_all(&colors)?;
+
+            if let Some(background) = background {
+                // tdesktop only looks for `background` and `tiled` images in these two formats
+                let extension = match background.image_type() {
+                    MediaType::Png => Some("png"),
+                    MediaType::Jpeg => Some("jpg"),
+                    _ => None,
+                };
+                if let (Some(extension), Some(image)) = (extension, background.image.to_file()?) {
+                    let stem = if background.tiled {
+                        "tiled"
+                    } else {
+                        "background"
+                    };
+                    archive.start_file(format!("{}.{}", stem, extension), options)?;
+                    archive.write_all(&image)?;
+                }
+            }
+
+            archive.finish()?;
+            Ok(())
+        };
+        write().map_err(|e| Error::from_io(e.into(), self.location.clone()))
+    }
+}